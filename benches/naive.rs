@@ -13,9 +13,9 @@ fn insert_and_lookup_standard(mut n: u64) {
     let mut hash_map = ::std::collections::HashMap::new();
 
     while n != 0 {
-        let key: String = (0..10).map(|_| rand::random::<u8>() as char).collect();
+        let key: String = (0..10).map(|_| rng.gen::<u8>() as char).collect();
         if rng.gen::<bool>() {
-            let value = Bytes::from((0..10).map(|_| rand::random::<u8>()).collect::<Vec<u8>>());
+            let value = Bytes::from((0..10).map(|_| rng.gen::<u8>()).collect::<Vec<u8>>());
             hash_map.insert(key, value);
         } else {
             hash_map.get(&key);
@@ -26,12 +26,13 @@ fn insert_and_lookup_standard(mut n: u64) {
 
 fn insert_and_lookup_naive(mut n: u64) {
     let mut rng: XorShiftRng = SeedableRng::from_seed([1981, 1986, 2003, 2011]);
-    let mut hash_map = lfu::LFU::new();
+    // fixed hasher seed, so that runs hash and iterate alike
+    let mut hash_map = lfu::LFU::with_seed(2019);
 
     while n != 0 {
-        let key: String = (0..10).map(|_| rand::random::<u8>() as char).collect();
+        let key: String = (0..10).map(|_| rng.gen::<u8>() as char).collect();
         if rng.gen::<bool>() {
-            let value = Bytes::from((0..10).map(|_| rand::random::<u8>()).collect::<Vec<u8>>());
+            let value = Bytes::from((0..10).map(|_| rng.gen::<u8>()).collect::<Vec<u8>>());
             hash_map.insert(key, value);
         } else {
             hash_map.get(&key);
@@ -52,6 +53,20 @@ macro_rules! insert_lookup {
     };
 }
 
+// low hit rate workload, every get misses a full cache
+fn lookup_all_miss(c: &mut Criterion) {
+    let mut cache = lfu::LFU::with_seed(2019).max_size(1024);
+    for i in 0..1024 {
+        cache.insert(format!("key{}", i), Bytes::from("42"));
+    }
+    let misses: Vec<String> = (0..1024).map(|i| format!("miss{}", i)).collect();
+    c.bench_function("LFU/all_miss/1024", move |b| b.iter(|| {
+        for key in &misses {
+            cache.get(key);
+        }
+    }));
+}
+
 insert_lookup!(insert_lookup_1, 1);
 insert_lookup!(insert_lookup_10, 10);
 insert_lookup!(insert_lookup_100, 100);
@@ -59,7 +74,7 @@ insert_lookup!(insert_lookup_100, 100);
 criterion_group!(
     name = benches;
     config = Criterion::default();
-    targets = insert_lookup_1, insert_lookup_10, insert_lookup_100
+    targets = insert_lookup_1, insert_lookup_10, insert_lookup_100, lookup_all_miss
 );
 
 criterion_main!(benches);
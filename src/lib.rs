@@ -12,61 +12,709 @@
 //!
 
 use bytes::Bytes;
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::io::Read;
+use std::mem::size_of;
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
 
+mod multi;
+
+pub use multi::MultiLFU;
+
+/// Frequency nodes form a doubly linked chain of strictly increasing values. Only the head
+/// (value 0) is allowed to be empty, any other node is unlinked as soon as its last item leaves.
 #[derive(Debug, Default)]
 struct FrequencyNode {
     // frequency node value
     pub value: u32,
-    items: Vec<String>,
+    items: Vec<Rc<String>>,
     next: Option<Rc<RefCell<FrequencyNode>>>,
-    // prev: Option<Weak<RefCell<FrequencyNode>>>
+    prev: Option<Weak<RefCell<FrequencyNode>>>
 }
 
 impl FrequencyNode {
     pub fn new(value: u32, next:Option<Rc<RefCell<FrequencyNode>>>) -> Self {
         FrequencyNode {
-            value, items: vec![], next
+            value, items: vec![], next, prev: None
+        }
+    }
+
+    // drops an emptied node from the chain, the head stays even when empty. Neighbours are
+    // borrowed only after the node's own borrow ended, a chain linking a node to itself panics
+    // with a message naming the broken invariant instead of an opaque double-borrow
+    fn unlink_if_empty(node: &Rc<RefCell<FrequencyNode>>, tail: &mut Rc<RefCell<FrequencyNode>>) {
+        let (prev, next) = {
+            let mut node = node.borrow_mut();
+            if !node.items.is_empty() {
+                return
+            }
+            let prev = match node.prev.as_ref().and_then(Weak::upgrade) {
+                Some(prev) => prev,
+                None => return,
+            };
+            node.prev = None;
+            (prev, node.next.take())
+        };
+        assert!(!Rc::ptr_eq(&prev, node), "frequency node linked as its own prev");
+        match &next {
+            Some(next) => {
+                assert!(!Rc::ptr_eq(next, node), "frequency node linked as its own next");
+                next.borrow_mut().prev = Some(Rc::downgrade(&prev));
+            }
+            None => *tail = prev.clone(),
+        }
+        prev.borrow_mut().next = next;
+    }
+}
+
+/// Key of the items map, sharing its string with the frequency node holding the item so that
+/// every key is stored only once. Hashes and compares like the `str` it wraps, so the map can
+/// be looked up by `&str`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Key(Rc<String>);
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_str().hash(state)
+    }
+}
+
+impl std::borrow::Borrow<str> for Key {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Key {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.0
+    }
+}
+
+/// Hasher of the items map, randomly keyed as usual or seeded for reproducible runs
+#[derive(Debug, Clone)]
+enum KeyHasher {
+    Random(RandomState),
+    Seeded(u64),
+}
+
+impl BuildHasher for KeyHasher {
+    type Hasher = DefaultHasher;
+
+    fn build_hasher(&self) -> DefaultHasher {
+        match self {
+            KeyHasher::Random(state) => state.build_hasher(),
+            KeyHasher::Seeded(seed) => {
+                let mut hasher = DefaultHasher::new();
+                hasher.write_u64(*seed);
+                hasher
+            }
         }
     }
 }
 
 /// original paper uses LFU Item but since this is private I see no reason for prefixing
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct Item {
     data: Bytes,
-    parent: Rc<RefCell<FrequencyNode>>
+    parent: Rc<RefCell<FrequencyNode>>,
+    inserted: Instant,
+    // insertion time until the first access
+    last_access: Instant,
+    accesses: u64,
+    // frequency as the Decaying policy counts it, decayed up to last_access
+    decayed: f64,
+    // accesses at frequency 0 which didn't earn a promotion yet
+    probation: u32,
+    // GDSF clock at the insert or last access
+    clock: f64,
+    // position in the order of inserts, for FIFO among equal frequencies
+    sequence: u64,
 }
 
 impl Item {
-    pub fn new(data: Bytes, parent: Rc<RefCell<FrequencyNode>>) -> Self {
-        Item {data, parent}
+    pub fn new(data: Bytes, parent: Rc<RefCell<FrequencyNode>>, clock: f64, now: Instant, sequence: u64) -> Self {
+        Item {data, parent, inserted: now, last_access: now, accesses: 0, decayed: 0.0, probation: 0, clock, sequence}
+    }
+
+    // GDSF priority, the clock it was last refreshed at plus frequency per byte. Frequency is
+    // counted from 1 and size from 1 byte so that fresh and empty values still compare.
+    fn priority(&self) -> f64 {
+        // in f64, as u32::MAX + 1 would overflow
+        let frequency = f64::from(self.parent.borrow().value) + 1.0;
+        self.clock + frequency / self.data.len().max(1) as f64
+    }
+
+    // decayed frequency halved for every `half_life` since the last access
+    fn decayed_frequency(&self, half_life: Duration, now: Instant) -> f64 {
+        let idle = now.duration_since(self.last_access).as_secs_f64();
+        self.decayed * 0.5f64.powf(idle / half_life.as_secs_f64())
+    }
+
+    // folds the decay since the last access into the count, has to run before last_access
+    // moves on, otherwise a single access would bring back the whole undecayed count
+    fn decay(&mut self, half_life: Duration, now: Instant) {
+        self.decayed = self.decayed_frequency(half_life, now);
+    }
+
+    // items at frequency 0 only move up on every `promote_after`-th access
+    fn earns_promotion(&mut self, promote_after: u32) -> bool {
+        if self.parent.borrow().value > 0 {
+            return true
+        }
+        self.probation += 1;
+        if self.probation < promote_after {
+            return false
+        }
+        self.probation = 0;
+        true
+    }
+
+    fn record_access(&mut self, now: Instant) {
+        self.last_access = now;
+        self.accesses += 1;
+    }
+
+    // moves the item (stored under `key`) to the node of the next frequency. Every node is
+    // borrowed on its own and no borrow outlives its statement, so the only way to panic here is
+    // the documented chain invariant failing rather than a `RefCell` conflict
+    fn promote(&mut self, key: &str, tail: &mut Rc<RefCell<FrequencyNode>>) {
+        let parent = self.parent.clone();
+        // pop the key and collect the handles needed below
+        let (key, value, next) = {
+            let mut parent_frequency_node = parent.borrow_mut();
+            let position = parent_frequency_node.items.iter().position(|x| x.as_str() == key);
+            let key = parent_frequency_node.items.remove(position.expect("item missing from its frequency node"));
+            (key, parent_frequency_node.value + 1, parent_frequency_node.next.clone())
+        };
+        if let Some(next) = &next {
+            assert!(!Rc::ptr_eq(next, &parent), "frequency node linked as its own next");
+        }
+        // provision next node unless the following one already has the next frequency
+        let next_frequency_node = match next {
+            Some(next) if next.borrow().value == value => next,
+            next => {
+                let mut node = FrequencyNode::new(value, next.clone());
+                node.prev = Some(Rc::downgrade(&parent));
+                let ref_cell = Rc::new(RefCell::new(node));
+                match next {
+                    Some(next) => next.borrow_mut().prev = Some(Rc::downgrade(&ref_cell)),
+                    None => *tail = ref_cell.clone(),
+                }
+                parent.borrow_mut().next = Some(ref_cell.clone());
+                ref_cell
+            }
+        };
+        next_frequency_node.borrow_mut().items.push(key);
+        self.parent = next_frequency_node;
+        FrequencyNode::unlink_if_empty(&parent, tail);
     }
 }
 
+// key at the top frequency and since when it holds the spot, weak so that removing the key
+// can still move its string out
+#[derive(Debug)]
+struct Reign {
+    key: Weak<String>,
+    since: Instant,
+}
+
+impl Reign {
+    // keeps the spot while the reigning key is still on the tail node and hands it to the first
+    // key there otherwise, so a key tying the top frequency never takes over whatever order the
+    // policy keeps the tail in. Called after every change of the chain
+    fn update(
+        reign: &mut Option<Reign>,
+        tail: &Rc<RefCell<FrequencyNode>>,
+        items: &HashMap<Key, Item, KeyHasher>,
+        now: Instant,
+    ) {
+        let reigning = reign.as_ref().and_then(|reign| reign.key.upgrade()).is_some_and(|key| {
+            items.get_key_value(key.as_str())
+                .is_some_and(|(stored, item)| Rc::ptr_eq(&stored.0, &key) && Rc::ptr_eq(&item.parent, tail))
+        });
+        if !reigning {
+            *reign = tail.borrow().items.first().map(|hottest| Reign { key: Rc::downgrade(hottest), since: now });
+        }
+    }
+
+    // the same after promoting a key, which is then mutably borrowed out of the items: the
+    // promoted key takes over only when it's alone on the tail, i.e. it overtook everyone else
+    fn promoted(reign: &mut Option<Reign>, tail: &Rc<RefCell<FrequencyNode>>, now: Instant) {
+        let tail = tail.borrow();
+        let hottest = match &tail.items[..] {
+            [hottest] => hottest,
+            _ => return,
+        };
+        if reign.as_ref().is_some_and(|reign| reign.key.as_ptr() == Rc::as_ptr(hottest)) {
+            return
+        }
+        *reign = Some(Reign { key: Rc::downgrade(hottest), since: now });
+    }
+}
+
+// keys of the most recent evictions, oldest first. A key forgotten on re-insert only leaves the
+// index, its slot in the queue goes stale and is skipped or compacted away later, so that
+// neither lookups nor inserts scan the queue
 #[derive(Debug, Default)]
+struct Ghosts {
+    queue: VecDeque<Rc<String>>,
+    index: HashSet<Key>,
+}
+
+impl Ghosts {
+    fn contains(&self, key: &str) -> bool {
+        self.index.contains(key)
+    }
+
+    // remembers an evicted key, forgetting the oldest ones beyond `capacity`
+    fn push(&mut self, key: String, capacity: usize) {
+        if capacity == 0 {
+            return
+        }
+        let key = Rc::new(key);
+        // a slot the key held before goes stale
+        self.index.replace(Key(key.clone()));
+        self.queue.push_back(key);
+        self.truncate(capacity);
+        // stale slots never outnumber live ones by much, keeping pushes amortized O(1)
+        if self.queue.len() > 2 * capacity {
+            let index = &self.index;
+            self.queue.retain(|key| Self::live(index, key));
+        }
+    }
+
+    fn forget(&mut self, key: &str) {
+        self.index.remove(key);
+    }
+
+    fn truncate(&mut self, capacity: usize) {
+        while self.index.len() > capacity {
+            let key = self.queue.pop_front().expect("ghost index outgrew its queue");
+            if Self::live(&self.index, &key) {
+                self.index.remove(key.as_str());
+            }
+        }
+    }
+
+    // whether a queue slot is the one the index points at
+    fn live(index: &HashSet<Key>, key: &Rc<String>) -> bool {
+        index.get(key.as_str()).is_some_and(|live| Rc::ptr_eq(&live.0, key))
+    }
+
+    fn clear(&mut self) {
+        self.queue.clear();
+        self.index.clear();
+    }
+
+    fn shrink_to_fit(&mut self) {
+        let index = &self.index;
+        self.queue.retain(|key| Self::live(index, key));
+        self.queue.shrink_to_fit();
+        self.index.shrink_to_fit();
+    }
+}
+
+/// Single step of an access trace, run by `LFU::apply` or replayed by `LFU::simulate`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Access {
+    Get(String),
+    Insert(String, Bytes),
+    Remove(String),
+}
+
+/// Outcome of replaying an access trace with `LFU::simulate`
+#[cfg(feature = "simulation")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SimulationResult {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+}
+
+/// Everything known about a single entry, yielded by `LFU::entries`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EntryInfo<'a> {
+    pub key: &'a String,
+    pub value: &'a Bytes,
+    pub frequency: u32,
+    /// time of the last access, or of the insert for entries never accessed
+    pub last_access: Instant,
+    /// number of accesses since the insert, unlike frequency this never ages
+    pub accesses: u64,
+}
+
+/// The entry the next eviction would take and why, see `LFU::next_eviction`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvictionCandidate {
+    pub key: String,
+    pub frequency: u32,
+    /// time since the insert
+    pub age: Duration,
+    pub reason: EvictionReason,
+}
+
+/// Why the eviction policy picked an `EvictionCandidate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    /// the only evictable entry at the lowest frequency
+    LowestFrequency,
+    /// one of several at the lowest frequency, picked as the oldest one by the policy's tiebreak
+    OldestTiebreak,
+    /// lowest GDSF priority
+    LowestPriority,
+    /// lowest frequency after decay
+    LowestDecayedFrequency,
+}
+
+impl std::fmt::Display for EvictionReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let reason = match self {
+            EvictionReason::LowestFrequency => "lowest frequency",
+            EvictionReason::OldestTiebreak => "oldest tiebreak",
+            EvictionReason::LowestPriority => "lowest GDSF priority",
+            EvictionReason::LowestDecayedFrequency => "lowest decayed frequency",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// Lookup and eviction counters, see `LFU::stats`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// chain corruptions found by `verify_on_mutation` in `OnCorruption::Report` mode
+    pub corruptions: u64,
+}
+
+impl CacheStats {
+    /// Share of gets which found their key, 0.0 before the first get
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
+/// How the victim is picked when the cache needs room, see `LFU::eviction`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// lowest frequency first, on ties the entry which reached that frequency first
+    #[default]
+    Lfu,
+    /// lowest frequency first, on ties the entry inserted first
+    LfuThenFifo,
+    /// Greedy-Dual-Size-Frequency, lowest `clock + frequency / size` first. The clock advances
+    /// to the priority of every victim, so entries not accessed for long eventually lose to
+    /// fresh ones. Priorities aren't kept in order, every eviction scans all entries, O(n).
+    GDSF,
+    /// lowest frequency first, with frequencies decaying exponentially while a key goes
+    /// unaccessed, see `LFU::effective_frequency`. Decay is computed when comparing, there's
+    /// no periodic sweep, so every eviction scans all entries, O(n).
+    Decaying { half_life: Duration },
+}
+
+/// What `verify_on_mutation` does when it finds the frequency chain corrupted, see
+/// `LFU::on_corruption`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnCorruption {
+    /// panic with a description of the corruption
+    #[default]
+    Panic,
+    /// count it in `CacheStats::corruptions` and carry on, with the log feature the
+    /// description is logged as an error
+    Report,
+}
+
+/// Reasons for `LFU::try_insert` to refuse a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertError {
+    /// the key is longer than max_key_len, lengths in bytes
+    KeyTooLong { len: usize, max: usize },
+}
+
+impl std::fmt::Display for InsertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            InsertError::KeyTooLong { len, max } => write!(f, "key of {} bytes is longer than the maximum of {}", len, max),
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+/// Source of the current time for everything time based in the cache, see `LFU::clock`
+pub trait Clock: std::fmt::Debug {
+    fn now(&self) -> Instant;
+}
+
+/// The default clock, reading `Instant::now`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock which only moves when told to, for tests. Clones share the time, so a clone kept
+/// outside the cache drives the one inside.
+///
+/// ```
+/// use lfu::{Clock, ManualClock};
+/// use std::time::Duration;
+/// let clock = ManualClock::new();
+/// let start = clock.now();
+/// clock.clone().advance(Duration::from_secs(1));
+/// assert_eq!(clock.now() - start, Duration::from_secs(1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Rc<Cell<Duration>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock { start: Instant::now(), elapsed: Rc::new(Cell::new(Duration::default())) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.elapsed.set(self.elapsed.get() + by);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+}
+
+/// Capacity state of an `LfuBuilder` which can't build yet
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NoCapacity;
+
+/// Capacity state of an `LfuBuilder` which can build, holds the chosen max_size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capacity(usize);
+
+/// Builder which only compiles `build` once a capacity was chosen, see `LFU::builder`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LfuBuilder<C> {
+    capacity: C,
+}
+
+impl LfuBuilder<NoCapacity> {
+    /// Sets the number of entries kept before evicting
+    pub fn max_size(self, size: usize) -> LfuBuilder<Capacity> {
+        LfuBuilder { capacity: Capacity(size) }
+    }
+
+    /// Never evicts for running out of entries
+    pub fn unbounded(self) -> LfuBuilder<Capacity> {
+        self.max_size(usize::MAX)
+    }
+}
+
+impl LfuBuilder<Capacity> {
+    /// Builds the cache, the remaining options chain on the result as for `LFU::new`
+    pub fn build(self) -> LFU {
+        LFU::new().max_size(self.capacity.0)
+    }
+}
+
+#[derive(Debug)]
 pub struct LFU {
     // main data storage, every cache can be usually thought of as a fixed size hashmap with extra method to evict certain keys when new value is added
-    items: HashMap<String, Item>,
+    items: HashMap<Key, Item, KeyHasher>,
     frequency_head: Rc<RefCell<FrequencyNode>>,
+    // last node of the chain, the head itself while nothing was accessed
+    frequency_tail: Rc<RefCell<FrequencyNode>>,
     max_size: usize,
     current_size: usize,
+    // debug-assert that an overwrite never changes the stored value
+    strict_overwrites: bool,
+    // how many frequency steps every item drops on each tick
+    aging_step: u32,
+    // share of the capacities each tick evicts down to
+    proactive_trim: Option<f64>,
+    // updating an existing key counts as an access
+    count_writes: bool,
+    // keys are lowercased on the way in
+    case_insensitive: bool,
+    // keys of the most recent evictions, oldest first, at most ghost_size of them
+    ghosts: Ghosts,
+    ghost_size: usize,
+    // accesses a frequency 0 item needs before it gets promoted
+    promote_after: u32,
+    // summed length of all keys, capped by max_key_bytes when set
+    key_bytes: usize,
+    max_key_bytes: Option<usize>,
+    // longer keys are rejected outright
+    max_key_len: Option<usize>,
+    // summed length of all values, capped by max_bytes when set. Mutable access through
+    // iter_mut leaves it stale until the next write refreshes it
+    value_bytes: usize,
+    value_bytes_stale: bool,
+    max_bytes: Option<usize>,
+    // entries younger than this are never picked for eviction
+    min_residency: Option<Duration>,
+    // values longer than this never gain frequency
+    no_promote_above_bytes: Option<usize>,
+    stats: CacheStats,
+    eviction: EvictionPolicy,
+    // priority of the last GDSF victim, new and accessed entries start from it
+    clock: f64,
+    time: Box<dyn Clock>,
+    reign: Option<Reign>,
+    // number of keys inserted so far, the sequence of the next insert
+    inserts: u64,
+    // checks the chain around every touched node and panics on corruption
+    verify_on_mutation: bool,
+    on_corruption: OnCorruption,
+}
+
+impl Default for LFU {
+    fn default() -> Self {
+        LFU::new()
+    }
 }
 
 impl LFU {
     pub fn new() -> Self {
-        let frequency_head = FrequencyNode::new(0, None);
+        let frequency_head = Rc::new(RefCell::new(FrequencyNode::new(0, None)));
         LFU {
-            items: HashMap::new(),
+            items: HashMap::with_hasher(KeyHasher::Random(RandomState::new())),
             max_size: 64,
             current_size: 0,
-            frequency_head: Rc::new(RefCell::new(frequency_head)),
+            strict_overwrites: false,
+            aging_step: 1,
+            proactive_trim: None,
+            count_writes: false,
+            case_insensitive: false,
+            ghosts: Ghosts::default(),
+            ghost_size: 0,
+            promote_after: 1,
+            key_bytes: 0,
+            max_key_bytes: None,
+            max_key_len: None,
+            value_bytes: 0,
+            value_bytes_stale: false,
+            max_bytes: None,
+            min_residency: None,
+            no_promote_above_bytes: None,
+            stats: CacheStats::default(),
+            eviction: EvictionPolicy::Lfu,
+            clock: 0.0,
+            time: Box::new(SystemClock),
+            reign: None,
+            inserts: 0,
+            verify_on_mutation: false,
+            on_corruption: OnCorruption::Panic,
+            frequency_tail: frequency_head.clone(),
+            frequency_head,
+        }
+    }
+    ///
+    /// Same as `new`, but the items map hashes with a fixed seed instead of random keys, so
+    /// that iteration order and hashing costs repeat from run to run, e.g. in benchmarks.
+    /// Not meant for keys from untrusted input, predictable hashes allow flooding attacks.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// let lfu = LFU::with_seed(42).max_size(1024);
+    /// ```
+    ///
+    pub fn with_seed(seed: u64) -> Self {
+        let mut lfu = LFU::new();
+        lfu.items = HashMap::with_hasher(KeyHasher::Seeded(seed));
+        lfu
+    }
+    ///
+    /// Cache of the given max_size pre-warmed with `(key, value, frequency)` entries, e.g.
+    /// restored from an export, every entry starting at its frequency. The chain is built
+    /// in one pass. A repeated key keeps its last entry, and past max_size the hottest entries
+    /// win, earlier ones among equal frequencies.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let entries = vec![("a".to_string(), Bytes::from("b"), 3), ("c".to_string(), Bytes::from("d"), 1)];
+    /// let mut lfu = LFU::from_frequencies(entries, 1);
+    /// assert_eq!(lfu.get_frequency("a"), 3);
+    /// assert!(!lfu.contains_key("c"));
+    /// ```
+    ///
+    pub fn from_frequencies(entries: impl IntoIterator<Item = (String, Bytes, u32)>, max_size: usize) -> Self {
+        let mut lfu = LFU::new().max_size(max_size);
+        let mut latest = HashMap::new();
+        for (position, (key, value, frequency)) in entries.into_iter().enumerate() {
+            latest.insert(lfu.normalize_key(&key).into_owned(), (position, value, frequency));
+        }
+        let mut entries: Vec<_> = latest.into_iter().collect();
+        entries.sort_by_key(|(_, (position, _, frequency))| (std::cmp::Reverse(*frequency), *position));
+        entries.truncate(max_size);
+        // inserted coldest first, so the head lists the keys in chain order
+        entries.sort_by_key(|(_, (position, _, frequency))| (*frequency, *position));
+        let mut frequencies = vec![];
+        for (key, (_, value, frequency)) in entries {
+            lfu.insert(key, value);
+            frequencies.push(frequency);
         }
+        let keys = std::mem::take(&mut lfu.frequency_head.borrow_mut().items);
+        let mut buckets: Vec<(u32, Vec<Rc<String>>)> = vec![];
+        for (key, frequency) in keys.into_iter().zip(frequencies) {
+            match buckets.last_mut() {
+                Some((value, keys)) if *value == frequency => keys.push(key),
+                _ => buckets.push((frequency, vec![key])),
+            }
+        }
+        lfu.rebuild_chain(buckets);
+        for item in lfu.items.values_mut() {
+            item.decayed = item.parent.borrow().value as f64;
+        }
+        lfu
+    }
+    ///
+    /// Builder which makes choosing a capacity a compile-time requirement, rather than
+    /// silently getting the default 64 of `new`
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// let lfu = LFU::builder().max_size(128).build().promote_after(2);
+    /// let unbounded = LFU::builder().unbounded().build();
+    /// ```
+    ///
+    /// ```compile_fail
+    /// use lfu::LFU;
+    /// let lfu = LFU::builder().build();
+    /// ```
+    ///
+    pub fn builder() -> LfuBuilder<NoCapacity> {
+        LfuBuilder { capacity: NoCapacity }
     }
     ///
-    /// Builder for max_size, only outside-configurable value for cache
+    /// Builder for max_size, the number of entries kept before evicting. Nothing is
+    /// pre-allocated up front, storage grows lazily, so `usize::MAX` can be used for unbounded.
     ///
     /// ```
     /// use lfu::LFU;
@@ -74,138 +722,4088 @@ impl LFU {
     /// ```
     ///
     pub fn max_size(mut self, size: usize) -> Self {
+        assert!(size > 0 || self.max_bytes != Some(0), "max_size and max_bytes can't both be 0");
         self.max_size = size;
         self
     }
     ///
-    /// Allows to check frequency for a key of given value
+    /// Builder for max_bytes, caps the summed length of all values on top of max_size. Both
+    /// limits hold at once: exceeding either one evicts, until both are satisfied again. A
+    /// value longer than the whole cap is not stored at all, updating a key to such a value
+    /// removes just that key.
+    ///
+    /// # Panics
+    ///
+    /// When both max_bytes and max_size end up 0, which could never store anything.
     ///
     /// ```
     /// use lfu::LFU;
     /// use bytes::Bytes;
-    /// let mut lfu = LFU::new();
-    /// lfu.get_frequency("a");
-    /// lfu.insert("a".to_string(), Bytes::from("b"));
-    /// assert_eq!(lfu.get_frequency("a"), 0);
-    /// lfu.get("a");
-    /// assert_eq!(lfu.get_frequency("a"), 1);
-    /// lfu.get("a");
-    /// assert_eq!(lfu.get_frequency("a"), 2);
-    /// lfu.get("a");
-    /// assert_eq!(lfu.get_frequency("a"), 3);
+    /// let mut lfu = LFU::new().max_size(10).max_bytes(4);
+    /// lfu.insert("a".to_string(), Bytes::from("bc"));
+    /// lfu.insert("d".to_string(), Bytes::from("efg"));
+    /// assert!(!lfu.contains_key("a"));
     /// ```
-    pub fn get_frequency(&mut self, key: &str) -> usize {
-        let mut counter = 0;
-        if self.items.contains_key(key){
-            let mut frequency_node = self.frequency_head.clone();
-            loop {
-                if frequency_node.borrow().next.is_none() {
-                    break
-                }
-                if frequency_node.borrow().items.iter().any(|f| f==key) {
-                    break
-                }
-                let tmp = frequency_node.borrow().next.as_ref().unwrap().clone();
-                frequency_node = tmp;
-                counter += 1;
-            }
-        }
-        counter
+    ///
+    pub fn max_bytes(mut self, bytes: usize) -> Self {
+        assert!(bytes > 0 || self.max_size > 0, "max_size and max_bytes can't both be 0");
+        self.max_bytes = Some(bytes);
+        self
     }
-
     ///
-    /// Get a Some(value) or None for a given key
+    /// Builder for max_key_bytes, caps the summed length of all keys independently of max_size,
+    /// evicting when an insert would go over. Meant for caches with large keys and small values.
+    /// A key longer than the whole cap is not stored at all.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_key_bytes(4);
+    /// lfu.insert("ab".to_string(), Bytes::from("b"));
+    /// lfu.insert("cde".to_string(), Bytes::from("d"));
+    /// assert!(!lfu.contains_key("ab"));
+    /// ```
+    ///
+    pub fn max_key_bytes(mut self, bytes: usize) -> Self {
+        self.max_key_bytes = Some(bytes);
+        self
+    }
+    ///
+    /// Builder for max_key_len, the longest key in bytes accepted at all, a guardrail for caches
+    /// keyed by external input. Longer keys are dropped by `insert` and reported by `try_insert`,
+    /// the cache stays as it was. Off by default.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_key_len(3);
+    /// lfu.insert("abcd".to_string(), Bytes::from("b"));
+    /// assert!(lfu.is_empty());
+    /// ```
+    ///
+    pub fn max_key_len(mut self, len: usize) -> Self {
+        self.max_key_len = Some(len);
+        self
+    }
     ///
+    /// Builder for min_residency, entries younger than the given duration are skipped when
+    /// picking an eviction victim, the next coldest old enough entry goes instead. When every
+    /// entry is too young a new key is refused, as if it were too long, so the capacity always
+    /// holds. Updates of stored keys still go through.
     ///
     /// ```
     /// use lfu::LFU;
     /// use bytes::Bytes;
-    /// let mut lfu = LFU::new();
-    /// assert_eq!(lfu.get("a"), None);
+    /// use std::time::Duration;
+    /// let mut lfu = LFU::new().max_size(1).min_residency(Duration::from_secs(60));
     /// lfu.insert("a".to_string(), Bytes::from("b"));
-    /// assert_eq!(lfu.get("a"), Some(&Bytes::from("b")));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// assert!(lfu.contains_key("a"));
+    /// assert!(!lfu.contains_key("c"));
     /// ```
-    pub fn get(&mut self, key: &str) -> Option<&Bytes> {
-        if let Some(item) = self.items.get_mut(key) {
-            item.parent = {
-                let mut parent_frequency_node = item.parent.borrow_mut();
-                // pop the key
-                parent_frequency_node.items.retain(|x| x != key);
-                // provision next node
-                if parent_frequency_node.next.is_none() {
-                    let next_freq = FrequencyNode::new(parent_frequency_node.value + 1, None);
-                    let ref_cell = Rc::new(RefCell::new(next_freq));
-                    parent_frequency_node.next = Some(ref_cell.clone());
-                }
-                let next_frequency_node = parent_frequency_node.next.as_ref().unwrap();
-                next_frequency_node.borrow_mut().items.push(key.to_owned());
-                next_frequency_node.clone()
-            };
-            Some(&item.data)
-        } else {
-            None
-        }
+    ///
+    pub fn min_residency(mut self, residency: Duration) -> Self {
+        self.min_residency = Some(residency);
+        self
     }
     ///
-    /// Insert a value into LFU
+    /// Builder for no_promote_above_bytes, values longer than the threshold don't gain frequency
+    /// on access, so large rarely useful blobs stay natural eviction candidates instead of
+    /// pushing out many small hot entries
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().no_promote_above_bytes(2);
+    /// lfu.insert("a".to_string(), Bytes::from("bcd"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 0);
+    /// ```
+    ///
+    pub fn no_promote_above_bytes(mut self, bytes: usize) -> Self {
+        self.no_promote_above_bytes = Some(bytes);
+        self
+    }
     ///
+    /// Builder for strict overwrites, meant for catching accidental key collisions
+    /// in composite keying schemes. With strict overwrites on, re-inserting an existing
+    /// key with a *different* value trips a `debug_assert!`, so it only fires in debug builds.
+    /// Re-inserting an identical value is always fine.
     ///
     /// ```
     /// use lfu::LFU;
     /// use bytes::Bytes;
-    /// let mut lfu = LFU::new();
+    /// let mut lfu = LFU::new().strict_overwrites(true);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
     /// lfu.insert("a".to_string(), Bytes::from("b"));
-    /// lfu.insert("a".to_string(), Bytes::from("z"));
-    /// assert_eq!(lfu.get("a"), Some(&Bytes::from("z")));
     /// ```
-    pub fn insert(&mut self, key: String, value: Bytes) -> Option<Bytes> {
-        let key_clone = key.clone();
-        let previous = match self.items.insert(key, Item::new(value, self.frequency_head.clone())){
-            Some(previous) => {
-                Some(previous.data)
-            },
-            None => None
-        };
-        self.frequency_head.borrow_mut().items.push(key_clone);
-        previous
+    ///
+    pub fn strict_overwrites(mut self, strict: bool) -> Self {
+        self.strict_overwrites = strict;
+        self
+    }
+    ///
+    /// Builder for aging step, how many frequency steps every item loses on each `tick`.
+    /// Defaults to 1, 0 turns aging off.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// let lfu = LFU::new().aging_step(2);
+    /// ```
+    ///
+    pub fn aging_step(mut self, step: u32) -> Self {
+        self.aging_step = step;
+        self
+    }
+    ///
+    /// Builder for proactive_trim, makes every `tick` evict down to `fraction` of max_size (and
+    /// of max_bytes when set), so that a later burst of inserts finds room without paying for
+    /// evictions. This evicts entries early, some of which may have been hit again.
+    ///
+    /// # Panics
+    ///
+    /// When `fraction` isn't within 0.0 and 1.0.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// let lfu = LFU::new().max_size(100).proactive_trim(0.9);
+    /// ```
+    ///
+    pub fn proactive_trim(mut self, fraction: f64) -> Self {
+        assert!((0.0..=1.0).contains(&fraction), "proactive_trim fraction {} not within 0.0 and 1.0", fraction);
+        self.proactive_trim = Some(fraction);
+        self
+    }
+    ///
+    /// Builder for count writes, when on an `insert` of an already present key bumps
+    /// its frequency the same way `get` does. Off by default, writes don't count as accesses.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// let lfu = LFU::new().count_writes(true);
+    /// ```
+    ///
+    pub fn count_writes(mut self, count: bool) -> Self {
+        self.count_writes = count;
+        self
+    }
+    ///
+    /// Builder for case insensitive keys, when on keys are lowercased on insert and on every
+    /// lookup so that `"Foo"` and `"foo"` are the same entry. Keys handed back by the cache
+    /// are the lowercased ones. Should be set before anything is inserted.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().case_insensitive(true);
+    /// lfu.insert("Content-Type".to_string(), Bytes::from("text/html"));
+    /// assert_eq!(lfu.get("content-type"), Some(&Bytes::from("text/html")));
+    /// ```
+    ///
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+    ///
+    /// Builder for ghost size, how many keys of recently evicted entries (without their values)
+    /// are remembered for `was_recently_evicted`. Defaults to 0, no history is kept.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// let lfu = LFU::new().ghost_size(16);
+    /// ```
+    ///
+    pub fn ghost_size(mut self, size: usize) -> Self {
+        self.ghost_size = size;
+        self.ghosts.truncate(size);
+        self
+    }
+    ///
+    /// Builder for promote after, a scan filter: a fresh (frequency 0) entry only moves up on
+    /// its n-th access, the accesses before that leave it a candidate for eviction. Defaults to 1,
+    /// every access promotes, 0 is treated as 1.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().promote_after(2);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 0);
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    ///
+    pub fn promote_after(mut self, accesses: u32) -> Self {
+        self.promote_after = accesses;
+        self
+    }
+    ///
+    /// Builder for the eviction policy, plain LFU by default. GDSF weighs frequency against
+    /// value size, preferring to keep many small warm values over one large one. Meant to be
+    /// set before inserting anything, LfuThenFifo orders its nodes as entries arrive.
+    ///
+    /// # Panics
+    ///
+    /// When a `Decaying` half-life is zero.
+    ///
+    /// ```
+    /// use lfu::{EvictionPolicy, LFU};
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(2).eviction(EvictionPolicy::GDSF);
+    /// lfu.insert("large".to_string(), Bytes::from(vec![0; 1000]));
+    /// lfu.insert("small".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// assert!(!lfu.contains_key("large"));
+    /// ```
+    ///
+    pub fn eviction(mut self, policy: EvictionPolicy) -> Self {
+        if let EvictionPolicy::Decaying { half_life } = policy {
+            assert!(half_life > Duration::default(), "Decaying half_life must be positive");
+        }
+        self.eviction = policy;
+        self
+    }
+    ///
+    /// Builder for the clock behind access times, min_residency and the hot key reign, the
+    /// system clock by default. Meant to be set before inserting anything.
+    ///
+    /// ```
+    /// use lfu::{LFU, ManualClock};
+    /// let clock = ManualClock::new();
+    /// let lfu = LFU::new().clock(clock.clone());
+    /// ```
+    ///
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.time = Box::new(clock);
+        self
+    }
+    ///
+    /// Builder for checking the frequency chain after every mutation, off by default. The
+    /// check only looks at the chain ends and the node a write touched, so it stays O(1). A
+    /// corruption is caught where it happens rather than showing up as wrong answers later,
+    /// by default with a panic describing it, see `on_corruption`. With the log feature the
+    /// description is also logged as an error.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// let lfu = LFU::new().verify_on_mutation(true);
+    /// ```
+    ///
+    pub fn verify_on_mutation(mut self, verify: bool) -> Self {
+        self.verify_on_mutation = verify;
+        self
+    }
+    ///
+    /// Builder for what verify_on_mutation does about a corrupted chain, panicking by default.
+    /// `OnCorruption::Report` counts it in the stats instead, for services which rather keep
+    /// serving than crash.
+    ///
+    /// ```
+    /// use lfu::{LFU, OnCorruption};
+    /// let lfu = LFU::new().verify_on_mutation(true).on_corruption(OnCorruption::Report);
+    /// assert_eq!(lfu.stats().corruptions, 0);
+    /// ```
+    ///
+    pub fn on_corruption(mut self, response: OnCorruption) -> Self {
+        self.on_corruption = response;
+        self
+    }
+    ///
+    /// Allows to check frequency for a key of given value
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.get_frequency("a");
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.get_frequency("a"), 0);
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 2);
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 3);
+    /// ```
+    pub fn get_frequency(&mut self, key: &str) -> usize {
+        let key = &*self.normalize_key(key);
+        self.items.get(key).map_or(0, |item| item.parent.borrow().value as usize)
+    }
+
+    ///
+    /// Checks whether a key is stored, without counting it as an access
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::new());
+    /// assert!(lfu.contains_key("a"));
+    /// assert!(!lfu.contains_key("b"));
+    /// assert_eq!(lfu.get_frequency("a"), 0);
+    /// ```
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.items.contains_key(&*self.normalize_key(key))
+    }
+
+    ///
+    /// Checks whether any key maps to the given value. This scans every entry, so it's O(n),
+    /// and doesn't count as an access.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert!(lfu.contains_value(&Bytes::from("b")));
+    /// assert!(!lfu.contains_value(&Bytes::from("a")));
+    /// ```
+    pub fn contains_value(&self, value: &Bytes) -> bool {
+        self.items.values().any(|item| item.data == *value)
+    }
+
+    ///
+    /// Checks whether the key was among the last `ghost_size` evictions and hasn't been
+    /// inserted again since, meant for admission decisions. A single hash lookup.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(1).ghost_size(4);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// assert!(lfu.was_recently_evicted("a"));
+    /// assert!(!lfu.was_recently_evicted("c"));
+    /// ```
+    pub fn was_recently_evicted(&self, key: &str) -> bool {
+        let key = &*self.normalize_key(key);
+        self.ghosts.contains(key)
+    }
+
+    ///
+    /// Number of entries in the cache, every entry takes one slot regardless of the value length
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.current_size
+    }
+
+    ///
+    /// Hits and misses of `get`, evictions and reported corruptions since creation or the last
+    /// `clear`
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// lfu.get("c");
+    /// assert_eq!(lfu.stats().hit_ratio(), 0.5);
+    /// ```
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    ///
+    /// Removes every entry and resets stats and the eviction history, configuration stays
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.clear();
+    /// assert!(lfu.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.clear_entries();
+        self.ghosts.clear();
+        self.stats = CacheStats::default();
+    }
+
+    // drops every entry, keeping stats and the eviction history
+    fn clear_entries(&mut self) {
+        self.items.clear();
+        self.rebuild_chain(vec![]);
+        self.current_size = 0;
+        self.key_bytes = 0;
+        self.value_bytes = 0;
+        self.value_bytes_stale = false;
+        self.clock = 0.0;
+    }
+
+    ///
+    /// Drops every value but keeps the keys at their frequencies, e.g. to flush payloads while
+    /// remembering what was hot. Values come back empty until re-inserted, and re-inserting a
+    /// key keeps the frequency it had.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// lfu.clear_values_keep_frequencies();
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// assert_eq!(lfu.get_bytes("a"), Some(&b""[..]));
+    /// ```
+    pub fn clear_values_keep_frequencies(&mut self) {
+        for item in self.items.values_mut() {
+            item.data = Bytes::new();
+        }
+        self.value_bytes = 0;
+        self.value_bytes_stale = false;
+    }
+
+    ///
+    /// Clears the cache when `f` holds for the current stats, e.g. to recover from a collapsed
+    /// hit ratio. Returns whether it cleared.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// assert!(!lfu.clear_if(|stats| stats.hit_ratio() < 0.5));
+    /// assert_eq!(lfu.len(), 1);
+    /// ```
+    pub fn clear_if<F: FnOnce(&CacheStats) -> bool>(&mut self, f: F) -> bool {
+        if !f(&self.stats) {
+            return false
+        }
+        self.clear();
+        true
+    }
+
+    ///
+    /// Swaps the whole content for `pairs` in one call, as removing every entry followed by
+    /// inserting every pair: configuration, stats and the eviction history stay, frequencies
+    /// start over at 0. Capacity applies as usual, so with more pairs than fit the later ones
+    /// evict earlier ones.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.replace_all(vec![("c".to_string(), Bytes::from("d"))]);
+    /// assert!(!lfu.contains_key("a"));
+    /// assert_eq!(lfu.get("c"), Some(&Bytes::from("d")));
+    /// ```
+    pub fn replace_all(&mut self, pairs: impl IntoIterator<Item = (String, Bytes)>) {
+        self.clear_entries();
+        for (key, value) in pairs {
+            self.insert(key, value);
+        }
+    }
+
+    ///
+    /// Recounts the entries from scratch and repairs the size counters, a recovery tool for when
+    /// a panic in the middle of an operation left it out of sync
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.recompute_size();
+    /// assert_eq!(lfu.len(), 1);
+    /// ```
+    pub fn recompute_size(&mut self) {
+        self.current_size = self.items.len();
+        self.key_bytes = self.items.keys().map(|key| key.len()).sum();
+        self.refresh_value_bytes();
+    }
+
+    ///
+    /// Checks whether the cache holds no entries
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// assert!(LFU::new().is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.current_size == 0
+    }
+
+    ///
+    /// Number of keys with frequency at or above `threshold`, handy for sizing a protected segment
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.working_set_size(0), 2);
+    /// assert_eq!(lfu.working_set_size(1), 1);
+    /// ```
+    pub fn working_set_size(&self, threshold: u32) -> usize {
+        let mut size = 0;
+        // walks down from the tail, only as far as the threshold reaches
+        let mut next = Some(self.frequency_tail.clone());
+        while let Some(node) = next {
+            let node = node.borrow();
+            if node.value < threshold {
+                break
+            }
+            size += node.items.len();
+            next = node.prev.as_ref().and_then(Weak::upgrade);
+        }
+        size
+    }
+
+    ///
+    /// The entry the next eviction would take, with the reason the policy picked it
+    ///
+    /// ```
+    /// use lfu::{EvictionReason, LFU};
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// let candidate = lfu.next_eviction().unwrap();
+    /// assert_eq!(candidate.key, "c");
+    /// assert_eq!(candidate.reason.to_string(), "lowest frequency");
+    /// ```
+    pub fn next_eviction(&self) -> Option<EvictionCandidate> {
+        let key = self.select_victim()?;
+        let item = &self.items[key.as_str()];
+        let now = self.time.now();
+        let reason = match self.eviction {
+            EvictionPolicy::Lfu | EvictionPolicy::LfuThenFifo => {
+                // the victim comes from the first node holding evictable entries
+                let tied = item.parent.borrow().items.iter().filter(|key| self.resident_long_enough(key, now)).count();
+                if tied > 1 {
+                    EvictionReason::OldestTiebreak
+                } else {
+                    EvictionReason::LowestFrequency
+                }
+            }
+            EvictionPolicy::GDSF => EvictionReason::LowestPriority,
+            EvictionPolicy::Decaying { .. } => EvictionReason::LowestDecayedFrequency,
+        };
+        Some(EvictionCandidate {
+            key: key.to_string(),
+            frequency: item.parent.borrow().value,
+            age: now.duration_since(item.inserted),
+            reason,
+        })
+    }
+
+    ///
+    /// Entry `n` places into the eviction order, 0 being the next victim, without touching it.
+    /// Follows the eviction policy and skips entries protected by min_residency, just like
+    /// eviction does. None when fewer entries could be evicted.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.peek_nth_coldest(0), Some((&"c".to_string(), &Bytes::from("d"))));
+    /// assert_eq!(lfu.peek_nth_coldest(2), None);
+    /// ```
+    pub fn peek_nth_coldest(&self, n: usize) -> Option<(&String, &Bytes)> {
+        let key = self.eviction_order().nth(n)?;
+        let (key, item) = self.items.get_key_value(key.as_str())?;
+        Some((key, &item.data))
+    }
+
+    ///
+    /// Frequency of the n-th entry counting up the frequency chain from the lowest, whatever the
+    /// eviction policy. That's the frequency of the last victim if room was made for `n` new
+    /// items under plain LFU without min_residency only, `peek_nth_coldest(n - 1)` tells the
+    /// policy's actual n-th victim. Counting starts at 1, returns None for 0 or when fewer than
+    /// `n` items exist.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.nth_coldest_frequency(1), Some(0));
+    /// assert_eq!(lfu.nth_coldest_frequency(2), Some(1));
+    /// assert_eq!(lfu.nth_coldest_frequency(3), None);
+    /// ```
+    pub fn nth_coldest_frequency(&self, n: usize) -> Option<u32> {
+        if n == 0 {
+            return None
+        }
+        let mut seen = 0;
+        for node in self.nodes() {
+            let node = node.borrow();
+            seen += node.items.len();
+            if seen >= n {
+                return Some(node.value)
+            }
+        }
+        None
+    }
+
+    ///
+    /// First key at the lowest frequency together with its frequency, whatever the eviction
+    /// policy. That's the next victim of plain LFU without min_residency only, `next_eviction`
+    /// or `peek_nth_coldest(0)` tell the actual next victim.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.coldest(), None);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.coldest(), Some((&"c".to_string(), 0)));
+    /// ```
+    pub fn coldest(&self) -> Option<(&String, u32)> {
+        // only the head may be empty, so this looks at two nodes at most
+        let node = self.nodes().find(|node| !node.borrow().items.is_empty())?;
+        let node = node.borrow();
+        let (key, _) = self.items.get_key_value(node.items.first()?.as_str())?;
+        Some((key, node.value))
+    }
+
+    ///
+    /// First key at the highest frequency together with its frequency. Ties go to the key which
+    /// reached the frequency first, or under `LfuThenFifo` to the one inserted first.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.hottest(), None);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.hottest(), Some((&"a".to_string(), 1)));
+    /// ```
+    pub fn hottest(&self) -> Option<(&String, u32)> {
+        let node = self.frequency_tail.borrow();
+        let (key, _) = self.items.get_key_value(node.items.first()?.as_str())?;
+        Some((key, node.value))
+    }
+
+    ///
+    /// The key holding the highest frequency together with that frequency and how long it has
+    /// been holding it, for spotting single-key hotspots. A key tying the frequency doesn't take
+    /// over the spot, only overtaking it does, so under `LfuThenFifo` this may name another key
+    /// than `hottest`, which orders ties by insertion.
+    ///
+    /// ```
+    /// use lfu::{LFU, ManualClock};
+    /// use bytes::Bytes;
+    /// use std::time::Duration;
+    /// let clock = ManualClock::new();
+    /// let mut lfu = LFU::new().clock(clock.clone());
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// clock.advance(Duration::from_secs(5));
+    /// assert_eq!(lfu.reigning_hot_key(), Some((&"a".to_string(), 1, Duration::from_secs(5))));
+    /// ```
+    pub fn reigning_hot_key(&self) -> Option<(&String, u32, Duration)> {
+        let reign = self.reign.as_ref()?;
+        let (key, item) = self.items.get_key_value(reign.key.upgrade()?.as_str())?;
+        Some((key, item.parent.borrow().value, self.time.now().duration_since(reign.since)))
+    }
+
+    ///
+    /// Frequency of the key as the `Decaying` policy sees it, halved for every half-life since
+    /// the last access. Every access adds 1 to the decayed value, so a long idle key doesn't get
+    /// its old count back with one access. Under the other policies it's the plain frequency.
+    /// None for absent keys.
+    ///
+    /// ```
+    /// use lfu::{EvictionPolicy, LFU, ManualClock};
+    /// use bytes::Bytes;
+    /// use std::time::Duration;
+    /// let clock = ManualClock::new();
+    /// let half_life = Duration::from_secs(60);
+    /// let mut lfu = LFU::new().clock(clock.clone()).eviction(EvictionPolicy::Decaying { half_life });
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// lfu.get("a");
+    /// clock.advance(half_life);
+    /// assert_eq!(lfu.effective_frequency("a"), Some(1.0));
+    /// ```
+    pub fn effective_frequency(&self, key: &str) -> Option<f64> {
+        let key = &*self.normalize_key(key);
+        let item = self.items.get(key)?;
+        Some(match self.eviction {
+            EvictionPolicy::Decaying { half_life } => item.decayed_frequency(half_life, self.time.now()),
+            _ => item.parent.borrow().value as f64,
+        })
+    }
+
+    ///
+    /// Up to `n` keys by descending frequency, like `Counter.most_common` in Python. Ties go to
+    /// the key which reached the frequency first, or under `LfuThenFifo` to the one inserted
+    /// first, the same order `hottest` reports.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("c");
+    /// assert_eq!(lfu.most_common(1), vec![("c".to_string(), 1)]);
+    /// ```
+    pub fn most_common(&self, n: usize) -> Vec<(String, u32)> {
+        let mut common = Vec::with_capacity(n.min(self.current_size));
+        // walks down from the tail, only as far as the n keys reach
+        let mut next = Some(self.frequency_tail.clone());
+        while let Some(node) = next {
+            if common.len() == n {
+                break
+            }
+            let node = node.borrow();
+            let wanted = n - common.len();
+            common.extend(node.items.iter().take(wanted).map(|key| (key.to_string(), node.value)));
+            next = node.prev.as_ref().and_then(Weak::upgrade);
+        }
+        common
+    }
+
+    ///
+    /// Popularity score of a key, its frequency divided by the highest frequency in the cache,
+    /// from 0.0 to 1.0 so that scores compare across caches. While nothing was accessed every
+    /// key is tied for the top and scores 1.0. None for absent keys.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// lfu.get("a");
+    /// lfu.get("c");
+    /// assert_eq!(lfu.score("a"), Some(1.0));
+    /// assert_eq!(lfu.score("c"), Some(0.5));
+    /// assert_eq!(lfu.score("e"), None);
+    /// ```
+    pub fn score(&self, key: &str) -> Option<f64> {
+        let item = self.items.get(&*self.normalize_key(key))?;
+        let max = self.frequency_tail.borrow().value;
+        if max == 0 {
+            return Some(1.0)
+        }
+        Some(f64::from(item.parent.borrow().value) / f64::from(max))
+    }
+
+    ///
+    /// Frequency values present in the chain, in chain order, with the number of keys each
+    /// holds. The head is left out while empty, so no pair ever has a zero count.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.chain_summary(), vec![(0, 1), (1, 1)]);
+    /// ```
+    pub fn chain_summary(&self) -> Vec<(u32, usize)> {
+        self.nodes()
+            .map(|node| {
+                let node = node.borrow();
+                (node.value, node.items.len())
+            })
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+
+    ///
+    /// Number of frequency nodes allocated above the head, the head is always there and isn't
+    /// counted. A count close to `len()` means a sparse chain with about one node per key.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.frequency_node_count(), 0);
+    /// lfu.get("a");
+    /// assert_eq!(lfu.frequency_node_count(), 1);
+    /// ```
+    pub fn frequency_node_count(&self) -> usize {
+        self.nodes().count() - 1
+    }
+
+    ///
+    /// Summed value bytes per frequency, shows which temperature of data takes the memory
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("bc"));
+    /// lfu.insert("d".to_string(), Bytes::from("e"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.bytes_by_frequency().into_iter().collect::<Vec<_>>(), vec![(0, 1), (1, 2)]);
+    /// ```
+    pub fn bytes_by_frequency(&self) -> BTreeMap<u32, usize> {
+        self.nodes()
+            .filter(|node| !node.borrow().items.is_empty())
+            .map(|node| {
+                let node = node.borrow();
+                let bytes = node.items.iter().map(|key| self.items[key.as_str()].data.len()).sum();
+                (node.value, bytes)
+            })
+            .collect()
+    }
+
+    ///
+    /// Estimated number of bytes spent on bookkeeping rather than on values: map entries,
+    /// key strings and frequency nodes. This is an estimate, allocator overhead isn't included.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// let empty = lfu.overhead_bytes();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert!(lfu.overhead_bytes() > empty);
+    /// ```
+    pub fn overhead_bytes(&self) -> usize {
+        // every map slot holds a key and an item plus a control byte, keys themselves are shared
+        // with the frequency nodes and only counted here
+        let map = self.items.capacity() * (size_of::<Key>() + size_of::<Item>() + 1)
+            + self.items.keys()
+                .map(|key| 2 * size_of::<usize>() + size_of::<String>() + key.capacity())
+                .sum::<usize>();
+        let chain: usize = self.nodes()
+            .map(|node| {
+                let node = node.borrow();
+                // Rc keeps strong and weak counts next to the node
+                2 * size_of::<usize>() + size_of::<RefCell<FrequencyNode>>()
+                    + node.items.capacity() * size_of::<Rc<String>>()
+            })
+            .sum();
+        map + chain
+    }
+
+    ///
+    /// Keys which would stay if capacity dropped to `new_max`, without evicting anything.
+    /// Victims are picked by the eviction policy, the survivors come in chain order, lowest
+    /// frequency first, which isn't the order later evictions would take them under every
+    /// policy.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.survivors_if_shrunk_to(1), vec!["a"]);
+    /// assert_eq!(lfu.len(), 2);
+    /// ```
+    pub fn survivors_if_shrunk_to(&self, new_max: usize) -> Vec<&String> {
+        let victims: HashSet<_> = self.eviction_order()
+            .take(self.current_size.saturating_sub(new_max))
+            .map(|victim| Rc::as_ptr(&victim))
+            .collect();
+        self.nodes()
+            .flat_map(|node| node.borrow().items.clone())
+            .filter(|key| !victims.contains(&Rc::as_ptr(key)))
+            .filter_map(|key| self.items.get_key_value(key.as_str()).map(|(key, _)| &**key))
+            .collect()
+    }
+
+    ///
+    /// Lowers (or raises) max_size, evicting right away until the cache fits
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.shrink_to(1);
+    /// assert_eq!(lfu.len(), 1);
+    /// ```
+    pub fn shrink_to(&mut self, new_max: usize) {
+        self.max_size = new_max;
+        while self.current_size > self.max_size && self.evict().is_some() {}
+    }
+
+    ///
+    /// Gives back memory left over from a spike: spare capacity of the items map, of every
+    /// frequency node and of the eviction history
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// for i in 0..64 {
+    ///     lfu.insert(i.to_string(), Bytes::from("b"));
+    /// }
+    /// for key in lfu.snapshot_keys() {
+    ///     lfu.remove(&key);
+    /// }
+    /// let before = lfu.overhead_bytes();
+    /// lfu.shrink_to_fit();
+    /// assert!(lfu.overhead_bytes() < before);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.items.shrink_to_fit();
+        for node in self.nodes() {
+            node.borrow_mut().items.shrink_to_fit();
+        }
+        self.ghosts.shrink_to_fit();
+    }
+
+    ///
+    /// Ages the cache by the configured aging step, meant to be called periodically
+    /// (e.g. from a timer) so that keys which stopped being accessed lose their frequency
+    /// and become eviction candidates. Frequencies saturate at 0. With proactive_trim set it
+    /// evicts down to the trim target afterwards.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// lfu.get("a");
+    /// lfu.tick();
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    pub fn tick(&mut self) {
+        let step = self.aging_step;
+        if step > 0 {
+            let buckets: Vec<_> = self.nodes()
+                .map(|node| {
+                    let mut node = node.borrow_mut();
+                    (node.value.saturating_sub(step), std::mem::take(&mut node.items))
+                })
+                .collect();
+            self.rebuild_chain(buckets);
+            for item in self.items.values_mut() {
+                item.decayed = (item.decayed - step as f64).max(0.0);
+            }
+        }
+        if let Some(fraction) = self.proactive_trim {
+            self.trim(fraction);
+        }
+    }
+
+    // evicts until the entries and, when capped, the value bytes are down to `fraction` of
+    // their capacity
+    fn trim(&mut self, fraction: f64) {
+        if self.value_bytes_stale {
+            self.refresh_value_bytes();
+        }
+        let max_size = (self.max_size as f64 * fraction) as usize;
+        let max_bytes = self.max_bytes.map(|max| (max as f64 * fraction) as usize);
+        while self.current_size > max_size || max_bytes.is_some_and(|max| self.value_bytes > max) {
+            if self.evict().is_none() {
+                break
+            }
+        }
+    }
+
+    ///
+    /// Get a Some(value) or None for a given key. A stored empty value is still a hit and
+    /// comes back as `Some` of an empty `Bytes`, only absent keys give None.
+    ///
+    /// A miss costs a single hash lookup and bumps the miss counter, nothing else: it doesn't
+    /// allocate, doesn't touch the frequency chain and doesn't read the clock. Exceptions are a
+    /// case insensitive cache lowercasing a key with upper case letters, and a trace logger
+    /// recording the miss with the log feature on.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// assert_eq!(lfu.get("a"), None);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.get("a"), Some(&Bytes::from("b")));
+    /// ```
+    pub fn get(&mut self, key: &str) -> Option<&Bytes> {
+        self.get_capped(key, u32::MAX)
+    }
+    ///
+    /// Same as `get`, but only promotes the key while its frequency is below `ceiling`. At the
+    /// ceiling the get still counts as a hit and an access, the frequency just stays put.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get_capped("a", 1);
+    /// assert_eq!(lfu.get_capped("a", 1), Some(&Bytes::from("b")));
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    pub fn get_capped(&mut self, key: &str, ceiling: u32) -> Option<&Bytes> {
+        let key = &*self.normalize_key(key);
+        self.access(key, ceiling, true).map(|item| &item.data)
+    }
+    ///
+    /// Same as `get`, but hands the value out as a plain byte slice for callers
+    /// that don't want to depend on the `bytes` crate
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.get_bytes("a"), Some(&b"b"[..]));
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    pub fn get_bytes(&mut self, key: &str) -> Option<&[u8]> {
+        self.get(key).map(|data| data.as_ref())
+    }
+    ///
+    /// Streams a value through `std::io::Read` without copying it out first, the access
+    /// is counted once when the reader is handed out
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// use std::io::Read;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("bcd"));
+    /// let mut value = String::new();
+    /// lfu.reader("a").unwrap().read_to_string(&mut value).unwrap();
+    /// assert_eq!(value, "bcd");
+    /// ```
+    pub fn reader(&mut self, key: &str) -> Option<impl Read + '_> {
+        self.get_bytes(key)
+    }
+    ///
+    /// Registers an access for every present key, as a `get` would without returning values.
+    /// Returns how many of the keys were found. This is a convenience wrapper rather than a
+    /// batched touch: keys are promoted one by one, each at the cost of a `get`.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.touch_many(&["a", "c", "a"]), 2);
+    /// assert_eq!(lfu.get_frequency("a"), 2);
+    /// ```
+    pub fn touch_many(&mut self, keys: &[&str]) -> usize {
+        keys.iter()
+            .filter(|key| {
+                let key = self.normalize_key(key);
+                self.access(&key, u32::MAX, false).is_some()
+            })
+            .count()
+    }
+    ///
+    /// Adds `by` to a counter stored as a little-endian u64, counting as an access of the key,
+    /// and returns the new count. Saturates at `u64::MAX` rather than wrapping. None, with
+    /// nothing changed, when the key is absent or its value isn't 8 bytes long.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::copy_from_slice(&1u64.to_le_bytes()));
+    /// assert_eq!(lfu.increment("a", 2), Some(3));
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    pub fn increment(&mut self, key: &str, by: u64) -> Option<u64> {
+        let key = &*self.normalize_key(key);
+        let data = &self.items.get(key)?.data;
+        if data.len() != 8 {
+            return None
+        }
+        let mut count = [0; 8];
+        count.copy_from_slice(data);
+        let count = u64::from_le_bytes(count).saturating_add(by);
+        self.access(key, u32::MAX, false);
+        self.items.get_mut(key)?.data = Bytes::copy_from_slice(&count.to_le_bytes());
+        Some(count)
+    }
+    ///
+    /// Insert a value into LFU
+    ///
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("a".to_string(), Bytes::from("z"));
+    /// assert_eq!(lfu.get("a"), Some(&Bytes::from("z")));
+    /// ```
+    ///
+    /// When the cache is full the least frequently used entry is evicted to make room,
+    /// the oldest one wins among entries of the same frequency.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(2);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// lfu.insert("e".to_string(), Bytes::from("f"));
+    /// assert_eq!(lfu.get("c"), None);
+    /// assert_eq!(lfu.len(), 2);
+    /// ```
+    pub fn insert(&mut self, key: String, value: Bytes) -> Option<Bytes> {
+        self.insert_evicting(key, value).0
+    }
+
+    ///
+    /// Same as `insert`, but reports a key rejected by max_key_len as an error rather than
+    /// dropping it silently
+    ///
+    /// ```
+    /// use lfu::{InsertError, LFU};
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_key_len(1);
+    /// assert_eq!(lfu.try_insert("a".to_string(), Bytes::from("b")), Ok(None));
+    /// assert_eq!(lfu.try_insert("cd".to_string(), Bytes::from("e")), Err(InsertError::KeyTooLong { len: 2, max: 1 }));
+    /// ```
+    pub fn try_insert(&mut self, key: String, value: Bytes) -> Result<Option<Bytes>, InsertError> {
+        let len = self.normalize_key(&key).len();
+        match self.max_key_len {
+            Some(max) if len > max => Err(InsertError::KeyTooLong { len, max }),
+            _ => Ok(self.insert(key, value)),
+        }
+    }
+
+    ///
+    /// Same as `insert`, but also reports what was evicted to make room, keeping "replaced the
+    /// same key" apart from "evicted other keys". Usually at most one entry is evicted, a key
+    /// byte cap can evict several for one long key.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(1);
+    /// let (previous, evicted) = lfu.insert_reporting("a".to_string(), Bytes::from("b"));
+    /// assert_eq!((previous, evicted), (None, vec![]));
+    /// let (previous, evicted) = lfu.insert_reporting("c".to_string(), Bytes::from("d"));
+    /// assert_eq!((previous, evicted), (None, vec![("a".to_string(), Bytes::from("b"))]));
+    /// ```
+    pub fn insert_reporting(&mut self, key: String, value: Bytes) -> (Option<Bytes>, Vec<(String, Bytes)>) {
+        self.insert_evicting(key, value)
+    }
+
+    ///
+    /// Admission step of TinyLFU: while the cache is full a new key is only inserted, evicting
+    /// the victims it needs room from, if `estimated_frequency` is strictly greater than the
+    /// frequency of every one of them. Under a byte cap that may be several victims. Returns
+    /// whether the value was stored. Updates of present keys and inserts
+    /// with room to spare always go through, the admitted key starts at frequency 0 as usual.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(1);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// assert!(!lfu.insert_if_better("c".to_string(), Bytes::from("d"), 1));
+    /// assert!(lfu.insert_if_better("c".to_string(), Bytes::from("d"), 2));
+    /// assert!(!lfu.contains_key("a"));
+    /// ```
+    pub fn insert_if_better(&mut self, key: String, value: Bytes, estimated_frequency: u32) -> bool {
+        let key = match self.normalize_key(&key) {
+            Cow::Owned(normalized) => normalized,
+            Cow::Borrowed(_) => key,
+        };
+        if self.value_bytes_stale {
+            self.refresh_value_bytes();
+        }
+        if !self.items.contains_key(key.as_str()) {
+            // every victim the insert would take has to be colder than the estimate
+            let (key_bytes, value_bytes) = (self.key_bytes + key.len(), self.value_bytes + value.len());
+            let colder = |_: &str, victim: &Item| estimated_frequency > victim.parent.borrow().value;
+            if !self.can_make_room(self.current_size, key_bytes, value_bytes, colder) {
+                return false
+            }
+        }
+        self.insert(key.clone(), value);
+        self.items.contains_key(key.as_str())
+    }
+
+    // inserts returning the replaced value and whatever got evicted to make room
+    fn insert_evicting(&mut self, key: String, value: Bytes) -> (Option<Bytes>, Vec<(String, Bytes)>) {
+        let key = match self.normalize_key(&key) {
+            Cow::Owned(normalized) => normalized,
+            Cow::Borrowed(_) => key,
+        };
+        if self.value_bytes_stale {
+            self.refresh_value_bytes();
+        }
+        if self.items.contains_key(key.as_str()) {
+            // the new value can never fit and the old one mustn't outlive the update, so only
+            // this key goes rather than everything evicted in front of it
+            if self.max_bytes.is_some_and(|max| value.len() > max) {
+                return (self.remove_entry(&key).map(|(_, previous)| previous), vec![])
+            }
+            // updates keep the frequency earned so far
+            if self.count_writes {
+                self.access(&key, u32::MAX, false);
+            }
+            let item = self.items.get_mut(key.as_str()).expect("entry vanished during update");
+            debug_assert!(!self.strict_overwrites || item.data == value, "key {:?} overwritten with a different value", key);
+            self.value_bytes = self.value_bytes + value.len() - item.data.len();
+            let previous = std::mem::replace(&mut item.data, value);
+            return (Some(previous), self.evict_to_max_bytes())
+        }
+        if !self.could_store(key.len(), value.len()) {
+            return (None, vec![])
+        }
+        // with entries protected by min_residency there may not be enough to evict, the new key
+        // is refused before anything goes rather than after
+        if self.min_residency.is_some() {
+            let (key_bytes, value_bytes) = (self.key_bytes + key.len(), self.value_bytes + value.len());
+            if !self.can_make_room(self.current_size, key_bytes, value_bytes, |_, _| true) {
+                return (None, vec![])
+            }
+        }
+        let mut evicted = vec![];
+        while self.needs_room(key.len(), value.len()) {
+            match self.evict() {
+                Some(entry) => evicted.push(entry),
+                // only reachable without min_residency on an empty cache, which could_store rules out
+                None => return (None, evicted),
+            }
+        }
+        self.ghosts.forget(&key);
+        #[cfg(feature = "log")]
+        log::trace!("insert {:?} at frequency 0", key);
+        let key = Rc::new(key);
+        self.frequency_head.borrow_mut().items.push(key.clone());
+        self.key_bytes += key.len();
+        self.value_bytes += value.len();
+        let now = self.time.now();
+        self.items.insert(Key(key.clone()), Item::new(value, self.frequency_head.clone(), self.clock, now, self.inserts));
+        self.inserts += 1;
+        self.current_size += 1;
+        Reign::update(&mut self.reign, &self.frequency_tail, &self.items, now);
+        self.verify(Some(&key));
+        (None, evicted)
+    }
+
+    ///
+    /// Best-effort warming which never disturbs what's cached: inserts pairs until the next one
+    /// would need room, then stops and returns how many were inserted. Keys already present
+    /// are skipped rather than overwritten.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new().max_size(2);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// let pairs = vec![("c".to_string(), Bytes::from("d")), ("e".to_string(), Bytes::from("f"))];
+    /// assert_eq!(lfu.fill(pairs), 1);
+    /// assert!(lfu.contains_key("a"));
+    /// ```
+    pub fn fill(&mut self, pairs: impl IntoIterator<Item = (String, Bytes)>) -> usize {
+        if self.value_bytes_stale {
+            self.refresh_value_bytes();
+        }
+        let mut inserted = 0;
+        for (key, value) in pairs {
+            let normalized = self.normalize_key(&key);
+            if self.items.contains_key(&*normalized) {
+                continue
+            }
+            if self.needs_room(normalized.len(), value.len()) {
+                break
+            }
+            let normalized = normalized.into_owned();
+            self.insert(key, value);
+            // keys refused by max_key_len and alike don't count
+            if self.items.contains_key(normalized.as_str()) {
+                inserted += 1;
+            }
+        }
+        inserted
+    }
+
+    ///
+    /// Replaces the value only if it currently equals `expected`, keeping the frequency and
+    /// counting no access. On a mismatch the current value comes back as `Err(Some(..))`,
+    /// an absent key gives `Err(None)`. A matching swap to a `new` value longer than max_bytes
+    /// removes just that key, as `insert` does for such a value, and reports `Err(None)`, so a
+    /// retry loop ends rather than retrying forever. A grown value that fits may still evict
+    /// others, and should the key itself be evicted the swap reports `Err(None)` too.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.compare_and_swap("a", &Bytes::from("b"), Bytes::from("c")), Ok(()));
+    /// assert_eq!(lfu.compare_and_swap("a", &Bytes::from("b"), Bytes::from("d")), Err(Some(Bytes::from("c"))));
+    /// ```
+    pub fn compare_and_swap(&mut self, key: &str, expected: &Bytes, new: Bytes) -> Result<(), Option<Bytes>> {
+        if self.value_bytes_stale {
+            self.refresh_value_bytes();
+        }
+        let key = &*self.normalize_key(key);
+        let item = self.items.get_mut(key).ok_or(None)?;
+        if item.data != *expected {
+            return Err(Some(item.data.clone()))
+        }
+        // never fits, the old value mustn't outlive the swap either
+        if self.max_bytes.is_some_and(|max| new.len() > max) {
+            self.remove_entry(key);
+            return Err(None)
+        }
+        self.value_bytes = self.value_bytes + new.len() - item.data.len();
+        item.data = new;
+        self.evict_to_max_bytes();
+        if self.items.contains_key(key) {
+            Ok(())
+        } else {
+            Err(None)
+        }
+    }
+
+    ///
+    /// Remove a key from LFU, returning its value if it was present
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.remove("a"), Some(Bytes::from("b")));
+    /// assert_eq!(lfu.remove("a"), None);
+    /// ```
+    pub fn remove(&mut self, key: &str) -> Option<Bytes> {
+        let key = &*self.normalize_key(key);
+        self.remove_entry(key).map(|(_, data)| data)
+    }
+
+    ///
+    /// Removes every present key of `keys`, returning the removed pairs in the order of `keys`.
+    /// Absent and repeated keys are skipped. The chain is cleaned up once for the whole batch,
+    /// every emptied frequency node is unlinked after all keys are out.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// assert_eq!(lfu.remove_many(&["a", "e"]), vec![("a".to_string(), Bytes::from("b"))]);
+    /// assert_eq!(lfu.len(), 1);
+    /// ```
+    pub fn remove_many(&mut self, keys: &[&str]) -> Vec<(String, Bytes)> {
+        let mut removed = Vec::with_capacity(keys.len());
+        let mut emptied = vec![];
+        for key in keys {
+            let (Key(key), item) = match self.items.remove_entry(&*self.normalize_key(key)) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let mut parent = item.parent.borrow_mut();
+            parent.items.retain(|x| !Rc::ptr_eq(x, &key));
+            if parent.items.is_empty() {
+                emptied.push(item.parent.clone());
+            }
+            drop(parent);
+            removed.push(self.discount(key, item));
+        }
+        for node in &emptied {
+            FrequencyNode::unlink_if_empty(node, &mut self.frequency_tail);
+        }
+        Reign::update(&mut self.reign, &self.frequency_tail, &self.items, self.time.now());
+        self.verify(None);
+        removed
+    }
+
+    ///
+    /// Iterates all entries together with their metadata, in no particular order
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// let entry = lfu.entries().next().unwrap();
+    /// assert_eq!(entry.key, "a");
+    /// assert_eq!(entry.frequency, 1);
+    /// ```
+    pub fn entries(&self) -> impl Iterator<Item = EntryInfo<'_>> {
+        self.items.iter().map(|(key, item)| EntryInfo {
+            key,
+            value: &item.data,
+            frequency: item.parent.borrow().value,
+            last_access: item.last_access,
+            accesses: item.accesses,
+        })
+    }
+
+    ///
+    /// Iterates keys with mutable values, in no particular order. Values live in the items map
+    /// rather than in the frequency nodes, so this never touches the chain and counts no access.
+    /// Changed lengths count against max_bytes from the next insert on.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// for (_, value) in lfu.iter_mut() {
+    ///     *value = Bytes::from("c");
+    /// }
+    /// assert_eq!(lfu.get("a"), Some(&Bytes::from("c")));
+    /// ```
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut Bytes)> {
+        self.value_bytes_stale = true;
+        self.items.iter_mut().map(|(key, item)| (&**key, &mut item.data))
+    }
+
+    ///
+    /// Consumes the cache into owned `(key, frequency, value)` triples, coldest first, keys of
+    /// a frequency in the order they reached it. Every entry is taken out of the cache as it's
+    /// yielded, so keys and values are released as iteration goes rather than at the end.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// lfu.get("a");
+    /// let entries: Vec<_> = lfu.into_sorted_by_frequency().collect();
+    /// assert_eq!(entries, vec![("c".to_string(), 0, Bytes::from("d")), ("a".to_string(), 1, Bytes::from("b"))]);
+    /// ```
+    pub fn into_sorted_by_frequency(mut self) -> impl Iterator<Item = (String, u32, Bytes)> {
+        let buckets: Vec<_> = self.nodes()
+            .map(|node| {
+                let mut node = node.borrow_mut();
+                (node.value, std::mem::take(&mut node.items))
+            })
+            .collect();
+        buckets.into_iter()
+            .flat_map(|(value, keys)| keys.into_iter().map(move |key| (value, key)))
+            .map(move |(value, key)| {
+                let (Key(stored), item) = self.items.remove_entry(key.as_str()).expect("chain key missing from items");
+                drop(key);
+                let key = Rc::try_unwrap(stored).unwrap_or_else(|key| (*key).clone());
+                (key, value, item.data)
+            })
+    }
+
+    ///
+    /// Moves an entry into another cache at the frequency it had here, dest's capacity and
+    /// eviction apply as for any insert. When dest already holds the key its value is replaced
+    /// and it keeps the higher of both frequencies. Returns false, leaving both caches as they
+    /// were, if the key wasn't present or dest can't take the entry, e.g. a key longer than its
+    /// max_key_len or no room left to evict under min_residency. That's checked up front, so a
+    /// refused transfer evicts nothing from dest.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut source = LFU::new();
+    /// let mut dest = LFU::new();
+    /// source.insert("a".to_string(), Bytes::from("b"));
+    /// source.get("a");
+    /// assert!(source.transfer("a", &mut dest));
+    /// assert!(!source.contains_key("a"));
+    /// assert_eq!(dest.get_frequency("a"), 1);
+    /// ```
+    pub fn transfer(&mut self, key: &str, dest: &mut LFU) -> bool {
+        let key = &*self.normalize_key(key);
+        let (frequency, data) = match self.items.get(key) {
+            Some(item) => (item.parent.borrow().value, item.data.clone()),
+            None => return false,
+        };
+        let dest_key = dest.normalize_key(key).into_owned();
+        if dest.value_bytes_stale {
+            dest.refresh_value_bytes();
+        }
+        // dest has to be able to take the entry before anything changes on either side
+        let room = match dest.items.get(dest_key.as_str()) {
+            // an update only evicts down to max_bytes, and must not evict the key itself
+            Some(held) => {
+                let value_bytes = dest.value_bytes - held.data.len() + data.len();
+                dest.max_bytes.is_none_or(|max| data.len() <= max)
+                    && dest.can_make_room(dest.current_size - 1, dest.key_bytes, value_bytes, |victim, _| victim != dest_key)
+            }
+            None => {
+                dest.could_store(dest_key.len(), data.len())
+                    && dest.can_make_room(dest.current_size, dest.key_bytes + dest_key.len(), dest.value_bytes + data.len(), |_, _| true)
+            }
+        };
+        if !room {
+            return false
+        }
+        dest.insert(dest_key.clone(), data);
+        // a write counted by dest stays counted
+        let held = dest.items.get(dest_key.as_str()).map_or(0, |item| item.parent.borrow().value);
+        dest.place_at_frequency(&dest_key, frequency.max(held));
+        self.remove_entry(key);
+        true
+    }
+
+    ///
+    /// Owned copy of all keys. Iterating the snapshot while looking up or removing each key
+    /// is the safe way of mutating the cache during iteration.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// for key in lfu.snapshot_keys() {
+    ///     lfu.remove(&key);
+    /// }
+    /// assert_eq!(lfu.get("a"), None);
+    /// ```
+    pub fn snapshot_keys(&self) -> Vec<String> {
+        self.items.keys().map(|key| key.to_string()).collect()
+    }
+
+    ///
+    /// Replaces every value with the output of `f`, keeping keys and frequencies as they are
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.map_values(|_, value| Bytes::from([&value[..], &value[..]].concat()));
+    /// assert_eq!(lfu.get("a"), Some(&Bytes::from("bb")));
+    /// ```
+    pub fn map_values<F: FnMut(&String, Bytes) -> Bytes>(&mut self, mut f: F) {
+        for (key, item) in self.items.iter_mut() {
+            let data = std::mem::take(&mut item.data);
+            item.data = f(key, data);
+        }
+        // values may have grown, the next write evicts down to max_bytes again
+        self.refresh_value_bytes();
+    }
+
+    ///
+    /// Renames every key to the output of `f`, keeping values and frequencies, e.g. to migrate
+    /// a key scheme in place. Entries are renamed coldest first and on a collision the later
+    /// one wins, so the hotter entry survives and the other one is dropped. Capacities aren't
+    /// re-checked until the next insert.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// lfu.map_keys(|key| format!("v2:{}", key));
+    /// assert_eq!(lfu.get_frequency("v2:a"), 1);
+    /// assert!(!lfu.contains_key("a"));
+    /// ```
+    pub fn map_keys<F: FnMut(&str) -> String>(&mut self, mut f: F) {
+        let hasher = self.items.hasher().clone();
+        let mut old_items = std::mem::replace(&mut self.items, HashMap::with_hasher(hasher));
+        let buckets: Vec<_> = self.nodes()
+            .map(|node| {
+                let mut node = node.borrow_mut();
+                (node.value, std::mem::take(&mut node.items))
+            })
+            .collect();
+        let mut renamed = Vec::with_capacity(buckets.len());
+        for (value, keys) in buckets {
+            let mut bucket = Vec::with_capacity(keys.len());
+            for key in keys {
+                let item = old_items.remove(key.as_str()).expect("item missing from the items map");
+                let new_key = f(&key);
+                let new_key = Rc::new(match self.normalize_key(&new_key) {
+                    Cow::Owned(normalized) => normalized,
+                    Cow::Borrowed(_) => new_key,
+                });
+                // the reign follows its key to the new name
+                if let Some(reign) = self.reign.as_mut().filter(|reign| reign.key.as_ptr() == Rc::as_ptr(&key)) {
+                    reign.key = Rc::downgrade(&new_key);
+                }
+                // replaces the colder entry renamed to the same key earlier, removed first as
+                // insert would keep its key
+                self.items.remove(new_key.as_str());
+                self.items.insert(Key(new_key.clone()), item);
+                bucket.push(new_key);
+            }
+            renamed.push((value, bucket));
+        }
+        // drop the keys of entries lost to a collision from their buckets
+        for (_, bucket) in renamed.iter_mut() {
+            bucket.retain(|key| self.items.get_key_value(key.as_str()).is_some_and(|(stored, _)| Rc::ptr_eq(&stored.0, key)));
+        }
+        self.rebuild_chain(renamed);
+        self.recompute_size();
+    }
+
+    ///
+    /// Lazily removes and yields entries matching `f`, one per `next` call, coldest first.
+    /// Entries which don't match stay in the cache. The chain is walked in place, nothing is
+    /// collected up front, and dropping the iterator early leaves the rest untouched.
+    ///
+    /// ```
+    /// use lfu::LFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("c".to_string(), Bytes::from("d"));
+    /// let drained: Vec<_> = lfu.drain_filter(|key, _| key == "a").collect();
+    /// assert_eq!(drained, vec![("a".to_string(), Bytes::from("b"))]);
+    /// assert_eq!(lfu.get("a"), None);
+    /// ```
+    pub fn drain_filter<'a, F>(&'a mut self, mut f: F) -> impl Iterator<Item = (String, Bytes)> + 'a
+    where
+        F: FnMut(&String, &Bytes) -> bool + 'a,
+    {
+        // walks the chain in place, the node and position of the next key to look at
+        let mut cursor = Some((self.frequency_head.clone(), 0));
+        std::iter::from_fn(move || {
+            while let Some((node, index)) = cursor.take() {
+                let key = node.borrow().items.get(index).cloned();
+                let key = match key {
+                    Some(key) => key,
+                    None => {
+                        cursor = node.borrow().next.clone().map(|next| (next, 0));
+                        continue
+                    }
+                };
+                let matches = self.items.get_key_value(key.as_str()).is_some_and(|(key, item)| f(key, &item.data));
+                if !matches {
+                    cursor = Some((node, index + 1));
+                    continue
+                }
+                // the following keys move up into the index, an emptied node gets unlinked and
+                // forgets its next, so the cursor moves on before that
+                let next = node.borrow().next.clone();
+                node.borrow_mut().items.remove(index);
+                cursor = if index < node.borrow().items.len() {
+                    Some((node, index))
+                } else {
+                    next.map(|next| (next, 0))
+                };
+                let (Key(stored), item) = self.items.remove_entry(key.as_str()).expect("chain key missing from items");
+                drop(key);
+                return Some(self.release(stored, item))
+            }
+            None
+        })
+    }
+
+    ///
+    /// Replays an access trace against the cache, counting hits and misses of gets and
+    /// entries evicted by inserts. Meant for evaluating the policy against real traces.
+    ///
+    /// ```
+    /// use lfu::{Access, LFU};
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// let result = lfu.simulate(&[Access::Insert("a".to_string(), Bytes::from("b")), Access::Get("a".to_string())]);
+    /// assert_eq!(result.hits, 1);
+    /// ```
+    #[cfg(feature = "simulation")]
+    pub fn simulate(&mut self, trace: &[Access]) -> SimulationResult {
+        let mut result = SimulationResult::default();
+        for access in trace {
+            match access {
+                Access::Get(key) => {
+                    if self.get(key).is_some() {
+                        result.hits += 1;
+                    } else {
+                        result.misses += 1;
+                    }
+                }
+                Access::Insert(key, value) => {
+                    // refused inserts and removed oversized updates aren't evictions
+                    let before = self.stats.evictions;
+                    self.insert(key.clone(), value.clone());
+                    result.evictions += (self.stats.evictions - before) as usize;
+                }
+                Access::Remove(key) => {
+                    self.remove(key);
+                }
+            }
+        }
+        result
+    }
+
+    ///
+    /// Runs a single recorded access through the matching method, for replaying workloads
+    ///
+    /// ```
+    /// use lfu::{Access, LFU};
+    /// use bytes::Bytes;
+    /// let mut lfu = LFU::new();
+    /// lfu.apply(Access::Insert("a".to_string(), Bytes::from("b")));
+    /// lfu.apply(Access::Get("a".to_string()));
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// lfu.apply(Access::Remove("a".to_string()));
+    /// assert!(lfu.is_empty());
+    /// ```
+    pub fn apply(&mut self, access: Access) {
+        match access {
+            Access::Get(key) => {
+                self.get(&key);
+            }
+            Access::Insert(key, value) => {
+                self.insert(key, value);
+            }
+            Access::Remove(key) => {
+                self.remove(&key);
+            }
+        }
+    }
+
+    // records an access of a (normalized) key, promoting it when due and below `ceiling`. A
+    // `lookup` counts as a hit or miss in the stats, from the same single map lookup
+    fn access(&mut self, key: &str, ceiling: u32, lookup: bool) -> Option<&Item> {
+        if self.eviction != EvictionPolicy::LfuThenFifo {
+            return self.count_access(key, ceiling, lookup).map(|(item, _)| &*item)
+        }
+        // the node a promotion lands in gets re-ordered through the items map, so the item is
+        // looked up again afterwards
+        let (item, promoted) = self.count_access(key, ceiling, lookup)?;
+        if promoted {
+            let parent = item.parent.clone();
+            self.keep_insertion_order(&parent);
+        }
+        self.items.get(key)
+    }
+
+    // the access itself, also telling whether the item got promoted
+    fn count_access(&mut self, key: &str, ceiling: u32, lookup: bool) -> Option<(&mut Item, bool)> {
+        let item = match self.items.get_mut(key) {
+            Some(item) => item,
+            None => {
+                if lookup {
+                    self.stats.misses += 1;
+                    #[cfg(feature = "log")]
+                    log::trace!("miss {:?}", key);
+                }
+                return None
+            }
+        };
+        if lookup {
+            self.stats.hits += 1;
+        }
+        let now = self.time.now();
+        if let EvictionPolicy::Decaying { half_life } = self.eviction {
+            item.decay(half_life, now);
+        }
+        item.record_access(now);
+        item.clock = self.clock;
+        let promotable = item.parent.borrow().value < ceiling
+            && self.no_promote_above_bytes.is_none_or(|max| item.data.len() <= max);
+        let promoted = promotable && item.earns_promotion(self.promote_after);
+        if promoted {
+            item.decayed += 1.0;
+            item.promote(key, &mut self.frequency_tail);
+            Reign::promoted(&mut self.reign, &self.frequency_tail, now);
+            if self.verify_on_mutation {
+                let touched = Some((key, &item.parent));
+                if let Some(problem) = Self::chain_corruption(&self.frequency_head, &self.frequency_tail, touched) {
+                    Self::corrupted(self.on_corruption, &mut self.stats, &problem);
+                }
+            }
+        }
+        Some((item, promoted))
+    }
+
+    // moves the key just pushed to the end of the node to its place by insert sequence, so that
+    // the nodes of LfuThenFifo stay in insertion order and the victim is always their first key
+    fn keep_insertion_order(&self, node: &Rc<RefCell<FrequencyNode>>) {
+        let mut node = node.borrow_mut();
+        let key = match node.items.pop() {
+            Some(key) => key,
+            None => return,
+        };
+        let sequence = self.items[key.as_str()].sequence;
+        let position = node.items.partition_point(|other| self.items[other.as_str()].sequence < sequence);
+        node.items.insert(position, key);
+    }
+
+    // checks whether an entry of the given key and value lengths fits the capacities at all,
+    // nothing could ever make room for one that doesn't
+    fn could_store(&self, key_len: usize, value_len: usize) -> bool {
+        let too_long = |len: usize, max: Option<usize>| max.is_some_and(|max| len > max);
+        !(self.max_size == 0
+            || too_long(key_len, self.max_key_bytes)
+            || too_long(key_len, self.max_key_len)
+            || too_long(value_len, self.max_bytes))
+    }
+
+    // checks whether a new entry of the given key and value lengths would go over any of the
+    // capacities
+    fn needs_room(&self, key_len: usize, value_len: usize) -> bool {
+        self.over_capacity(self.current_size, self.key_bytes + key_len, self.value_bytes + value_len)
+    }
+
+    // checks whether evicting in eviction order makes room for an entry, given the totals it
+    // would bring the cache to, as over_capacity takes them. Every victim on the way has to
+    // pass `admit`, running out of evictable entries fails too
+    fn can_make_room<F: FnMut(&str, &Item) -> bool>(&self, mut size: usize, mut key_bytes: usize, mut value_bytes: usize, mut admit: F) -> bool {
+        let mut victims = self.eviction_order();
+        while self.over_capacity(size, key_bytes, value_bytes) {
+            let victim = match victims.next() {
+                Some(victim) => victim,
+                None => return false,
+            };
+            let item = &self.items[victim.as_str()];
+            if !admit(&victim, item) {
+                return false
+            }
+            size -= 1;
+            key_bytes -= victim.len();
+            value_bytes -= item.data.len();
+        }
+        true
+    }
+
+    // checks whether adding an entry to `size` others goes over any of the capacities, the byte
+    // totals already including the new entry
+    fn over_capacity(&self, size: usize, key_bytes: usize, value_bytes: usize) -> bool {
+        size >= self.max_size
+            || self.max_key_bytes.is_some_and(|max| key_bytes > max)
+            || self.max_bytes.is_some_and(|max| value_bytes > max)
+    }
+
+    // evicts after a value grew in place until max_bytes holds again, the grown entry itself
+    // may go
+    fn evict_to_max_bytes(&mut self) -> Vec<(String, Bytes)> {
+        let mut evicted = vec![];
+        while self.max_bytes.is_some_and(|max| self.value_bytes > max) {
+            match self.evict() {
+                Some(entry) => evicted.push(entry),
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    fn refresh_value_bytes(&mut self) {
+        self.value_bytes = self.items.values().map(|item| item.data.len()).sum();
+        self.value_bytes_stale = false;
+    }
+
+    // picks the item old enough to be evicted which the policy ranks first, for LFU the least
+    // frequently used one, the oldest one of its frequency node
+    fn select_victim(&self) -> Option<Rc<String>> {
+        match self.eviction {
+            // lazy, only the coldest node gets looked at
+            EvictionPolicy::Lfu | EvictionPolicy::LfuThenFifo => self.eviction_order().next(),
+            // a single scan rather than sorting everything
+            EvictionPolicy::GDSF | EvictionPolicy::Decaying { .. } => {
+                let now = self.time.now();
+                self.evictable().min_by(|a, b| self.priority(a, now).total_cmp(&self.priority(b, now)))
+            }
+        }
+    }
+
+    // keys in the order repeated evictions would take them, entries protected by min_residency
+    // never show up
+    fn eviction_order(&self) -> Box<dyn Iterator<Item = Rc<String>> + '_> {
+        match self.eviction {
+            // LfuThenFifo keeps its nodes in insertion order
+            EvictionPolicy::Lfu | EvictionPolicy::LfuThenFifo => Box::new(self.evictable()),
+            EvictionPolicy::GDSF | EvictionPolicy::Decaying { .. } => {
+                let now = self.time.now();
+                let mut keys: Vec<_> = self.evictable().collect();
+                // stable, so equal priorities keep chain order
+                keys.sort_by(|a, b| self.priority(a, now).total_cmp(&self.priority(b, now)));
+                Box::new(keys.into_iter())
+            }
+        }
+    }
+
+    // keys in chain order, leaving out entries protected by min_residency
+    fn evictable(&self) -> impl Iterator<Item = Rc<String>> + '_ {
+        let now = self.time.now();
+        self.nodes()
+            .flat_map(|node| {
+                let len = node.borrow().items.len();
+                (0..len).map(move |index| node.borrow().items[index].clone())
+            })
+            .filter(move |key| self.resident_long_enough(key, now))
+    }
+
+    // whether min_residency, if set, allows evicting the key
+    fn resident_long_enough(&self, key: &str, now: Instant) -> bool {
+        self.min_residency.is_none_or(|min| now.duration_since(self.items[key].inserted) >= min)
+    }
+
+    // what the scored policies rank by, lowest evicted first, the plain frequency otherwise
+    fn priority(&self, key: &str, now: Instant) -> f64 {
+        let item = &self.items[key];
+        match self.eviction {
+            EvictionPolicy::GDSF => item.priority(),
+            EvictionPolicy::Decaying { half_life } => item.decayed_frequency(half_life, now),
+            EvictionPolicy::Lfu | EvictionPolicy::LfuThenFifo => item.parent.borrow().value as f64,
+        }
+    }
+
+    // removes the least frequently used item, the oldest one of its frequency node
+    fn evict(&mut self) -> Option<(String, Bytes)> {
+        let key = self.select_victim()?;
+        self.stats.evictions += 1;
+        #[cfg(feature = "log")]
+        log::debug!("evict {:?} at frequency {}", key, self.items[key.as_str()].parent.borrow().value);
+        if self.eviction == EvictionPolicy::GDSF {
+            self.clock = self.items[key.as_str()].priority();
+        }
+        self.ghosts.push(key.to_string(), self.ghost_size);
+        self.remove_entry(&key)
+    }
+
+    // removes the item together with its key from the frequency chain
+    fn remove_entry(&mut self, key: &str) -> Option<(String, Bytes)> {
+        let (Key(key), item) = self.items.remove_entry(key)?;
+        item.parent.borrow_mut().items.retain(|x| !Rc::ptr_eq(x, &key));
+        Some(self.release(key, item))
+    }
+
+    // bookkeeping for an entry taken out of both the items map and its frequency node, `key`
+    // has to be the last handle on its string for the string to be moved out rather than cloned
+    fn release(&mut self, key: Rc<String>, item: Item) -> (String, Bytes) {
+        FrequencyNode::unlink_if_empty(&item.parent, &mut self.frequency_tail);
+        Reign::update(&mut self.reign, &self.frequency_tail, &self.items, self.time.now());
+        self.verify(None);
+        self.discount(key, item)
+    }
+
+    // takes a released entry off the size counters and moves its key out
+    fn discount(&mut self, key: Rc<String>, item: Item) -> (String, Bytes) {
+        self.current_size -= 1;
+        self.key_bytes -= key.len();
+        // a stale count gets recomputed as a whole anyway
+        if !self.value_bytes_stale {
+            self.value_bytes -= item.data.len();
+        }
+        // the frequency node let go of its copy, so the string can be moved out
+        let key = Rc::try_unwrap(key).unwrap_or_else(|key| (*key).clone());
+        (key, item.data)
+    }
+
+    // moves a stored item to the node of the given frequency, creating that node when missing
+    fn place_at_frequency(&mut self, key: &str, frequency: u32) {
+        let item = match self.items.get_mut(key) {
+            Some(item) => item,
+            None => return,
+        };
+        let parent = item.parent.clone();
+        let key = {
+            let mut parent = parent.borrow_mut();
+            let position = parent.items.iter().position(|x| x.as_str() == key);
+            parent.items.remove(position.expect("item missing from its frequency node"))
+        };
+        // last node at or below the frequency, the head always qualifies
+        let mut node = self.frequency_head.clone();
+        loop {
+            let next = match &node.borrow().next {
+                Some(next) if next.borrow().value <= frequency => next.clone(),
+                _ => break,
+            };
+            node = next;
+        }
+        if node.borrow().value != frequency {
+            let next = node.borrow_mut().next.take();
+            let ref_cell = Rc::new(RefCell::new(FrequencyNode::new(frequency, next.clone())));
+            ref_cell.borrow_mut().prev = Some(Rc::downgrade(&node));
+            match next {
+                Some(next) => next.borrow_mut().prev = Some(Rc::downgrade(&ref_cell)),
+                None => self.frequency_tail = ref_cell.clone(),
+            }
+            node.borrow_mut().next = Some(ref_cell.clone());
+            node = ref_cell;
+        }
+        node.borrow_mut().items.push(key);
+        item.parent = node.clone();
+        item.decayed = frequency as f64;
+        if self.eviction == EvictionPolicy::LfuThenFifo {
+            self.keep_insertion_order(&node);
+        }
+        FrequencyNode::unlink_if_empty(&parent, &mut self.frequency_tail);
+        Reign::update(&mut self.reign, &self.frequency_tail, &self.items, self.time.now());
+        self.verify(None);
+    }
+
+    // replaces the chain with fresh nodes built from (frequency, keys) buckets, buckets have to be
+    // sorted by frequency but may repeat one, they get merged
+    fn rebuild_chain<I: IntoIterator<Item = (u32, Vec<Rc<String>>)>>(&mut self, buckets: I) {
+        let head = Rc::new(RefCell::new(FrequencyNode::new(0, None)));
+        let mut tail = head.clone();
+        for (value, keys) in buckets {
+            if keys.is_empty() {
+                continue
+            }
+            if tail.borrow().value != value {
+                let node = Rc::new(RefCell::new(FrequencyNode::new(value, None)));
+                node.borrow_mut().prev = Some(Rc::downgrade(&tail));
+                tail.borrow_mut().next = Some(node.clone());
+                tail = node;
+            }
+            for key in &keys {
+                if let Some(item) = self.items.get_mut(key.as_str()) {
+                    item.parent = tail.clone();
+                }
+            }
+            tail.borrow_mut().items.extend(keys);
+            if self.eviction == EvictionPolicy::LfuThenFifo {
+                tail.borrow_mut().items.sort_by_key(|key| self.items[key.as_str()].sequence);
+            }
+        }
+        self.frequency_head = head;
+        self.frequency_tail = tail;
+        Reign::update(&mut self.reign, &self.frequency_tail, &self.items, self.time.now());
+        self.verify(None);
+    }
+
+    // runs the chain check with verify_on_mutation set, around the node of `key` if given
+    fn verify(&mut self, key: Option<&str>) {
+        if !self.verify_on_mutation {
+            return
+        }
+        let touched = key.and_then(|key| self.items.get_key_value(key)).map(|(key, item)| (key.as_str(), &item.parent));
+        if let Some(problem) = Self::chain_corruption(&self.frequency_head, &self.frequency_tail, touched) {
+            Self::corrupted(self.on_corruption, &mut self.stats, &problem);
+        }
+    }
+
+    // cheap subset of the chain invariants: sound chain ends and, for a touched key, that its
+    // node holds it and is linked both ways with increasing neighbours. Describes the first
+    // violation found
+    fn chain_corruption(
+        head: &Rc<RefCell<FrequencyNode>>,
+        tail: &Rc<RefCell<FrequencyNode>>,
+        touched: Option<(&str, &Rc<RefCell<FrequencyNode>>)>,
+    ) -> Option<String> {
+        {
+            let head = head.borrow();
+            if head.value != 0 {
+                return Some(format!("head has frequency {}", head.value))
+            }
+            if head.prev.is_some() {
+                return Some("head has a prev link".to_string())
+            }
+        }
+        if let Some(next) = &tail.borrow().next {
+            return Some(format!("tail at frequency {} links to frequency {}", tail.borrow().value, next.borrow().value))
+        }
+        let (key, node) = touched?;
+        let node_ref = node.borrow();
+        if !node_ref.items.iter().any(|x| x.as_str() == key) {
+            return Some(format!("key {:?} missing from its node at frequency {}", key, node_ref.value))
+        }
+        match node_ref.prev.as_ref().and_then(Weak::upgrade) {
+            Some(prev) if Rc::ptr_eq(&prev, node) => {
+                return Some(format!("node at frequency {} linked as its own prev", node_ref.value))
+            }
+            Some(prev) => {
+                let prev = prev.borrow();
+                if prev.value >= node_ref.value || !prev.next.as_ref().is_some_and(|next| Rc::ptr_eq(next, node)) {
+                    return Some(format!("node at frequency {} and its prev at {} aren't linked in order", node_ref.value, prev.value))
+                }
+            }
+            None if !Rc::ptr_eq(node, head) => {
+                return Some(format!("node at frequency {} has no prev but isn't the head", node_ref.value))
+            }
+            None => {}
+        }
+        match &node_ref.next {
+            Some(next) if Rc::ptr_eq(next, node) => {
+                Some(format!("node at frequency {} linked as its own next", node_ref.value))
+            }
+            Some(next) => {
+                let next = next.borrow();
+                let back = next.prev.as_ref().and_then(Weak::upgrade).is_some_and(|prev| Rc::ptr_eq(&prev, node));
+                if next.value <= node_ref.value || !back {
+                    return Some(format!("node at frequency {} and its next at {} aren't linked in order", node_ref.value, next.value))
+                }
+                None
+            }
+            None if !Rc::ptr_eq(node, tail) => {
+                Some(format!("node at frequency {} ends the chain but isn't the tail", node_ref.value))
+            }
+            None => None,
+        }
+    }
+
+    // takes the stats rather than self, so that it can be called while an item is borrowed
+    fn corrupted(response: OnCorruption, stats: &mut CacheStats, problem: &str) {
+        #[cfg(feature = "log")]
+        log::error!("frequency chain corrupted: {}", problem);
+        match response {
+            OnCorruption::Panic => panic!("frequency chain corrupted: {}", problem),
+            OnCorruption::Report => stats.corruptions += 1,
+        }
+    }
+
+    // lowercases keys of a case insensitive cache, only allocating when something changes
+    fn normalize_key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        if self.case_insensitive && key.chars().any(|c| c.to_lowercase().ne(std::iter::once(c))) {
+            Cow::Owned(key.to_lowercase())
+        } else {
+            Cow::Borrowed(key)
+        }
+    }
+
+    // walks the frequency chain starting from the head
+    fn nodes(&self) -> impl Iterator<Item = Rc<RefCell<FrequencyNode>>> {
+        std::iter::successors(Some(self.frequency_head.clone()), |node| node.borrow().next.clone())
+            .inspect(|_| {
+                #[cfg(test)]
+                NODES_WALKED.with(|walked| walked.set(walked.get() + 1));
+            })
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    // frequency nodes visited by `nodes`, lets tests assert that a method doesn't walk the chain
+    static NODES_WALKED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quickcheck;
+
+    use crate::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn it_works() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.get("a"), Some(&Bytes::from("42")));
+        print!("{:?}", lfu);
+    }
+    #[test]
+    fn test_max_size() {
+        let lfu = LFU::new().max_size(1000);
+        assert_eq!(lfu.max_size, 1000);
+    }
+
+    #[test]
+    fn test_evictions() {
+        let mut lfu = LFU::new().max_size(3);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        println!("{:?}", lfu);
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        lfu.get("a");
+        lfu.get("a");
+        lfu.get("c");
+        lfu.insert("d".to_string(), Bytes::from("45"));
+        assert_eq!(lfu.len(), 3);
+        assert!(!lfu.contains_key("b"));
+        lfu.insert("e".to_string(), Bytes::from("46"));
+        assert!(!lfu.contains_key("d"));
+        assert!(lfu.contains_key("a"));
+        assert!(lfu.contains_key("c"));
+        assert!(lfu.contains_key("e"));
+        // updating a key never evicts
+        lfu.insert("a".to_string(), Bytes::from("47"));
+        assert_eq!(lfu.len(), 3);
+        assert!(lfu.contains_key("e"));
+    }
+
+    #[test]
+    fn test_zero_max_size() {
+        let mut lfu = LFU::new().max_size(0);
+        assert_eq!(lfu.insert("a".to_string(), Bytes::from("42")), None);
+        assert!(lfu.is_empty());
+    }
+
+    #[test]
+    fn test_frequency() {
+        let mut lfu = LFU::new().max_size(3);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.get("a");
+        lfu.get("a");
+        assert_eq!(lfu.get_frequency("a"), 2);
+        println!("{:?}", lfu);
+    }
+
+    #[test]
+    fn test_strict_overwrites_identical_value() {
+        let mut lfu = LFU::new().strict_overwrites(true);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.insert("a".to_string(), Bytes::from("42")), Some(Bytes::from("42")));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "overwritten with a different value")]
+    fn test_strict_overwrites_different_value() {
+        let mut lfu = LFU::new().strict_overwrites(true);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("a".to_string(), Bytes::from("43"));
+    }
+
+    #[test]
+    fn test_tick() {
+        let mut lfu = LFU::new().aging_step(1);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        for _ in 0..3 {
+            lfu.get("a");
+        }
+        lfu.get("b");
+        lfu.tick();
+        assert_eq!(lfu.get_frequency("a"), 2);
+        assert_eq!(lfu.get_frequency("b"), 0);
+        lfu.tick();
+        lfu.tick();
+        assert_eq!(lfu.get_frequency("a"), 0);
+        lfu.get("a");
+        assert_eq!(lfu.get_frequency("a"), 1);
+    }
+
+    #[test]
+    fn test_tick_aging_step() {
+        let mut lfu = LFU::new().aging_step(2);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        for _ in 0..5 {
+            lfu.get("a");
+        }
+        lfu.tick();
+        assert_eq!(lfu.get_frequency("a"), 3);
+        lfu.tick();
+        assert_eq!(lfu.get_frequency("a"), 1);
+        let mut lfu = lfu.aging_step(0);
+        lfu.tick();
+        assert_eq!(lfu.get_frequency("a"), 1);
+    }
+
+    #[test]
+    fn test_working_set_size() {
+        let mut lfu = LFU::new();
+        insert_accessed(&mut lfu, &[("a", 0), ("b", 1), ("c", 1), ("d", 3), ("e", 5)]);
+        assert_eq!(lfu.working_set_size(0), 5);
+        assert_eq!(lfu.working_set_size(1), 4);
+        assert_eq!(lfu.working_set_size(2), 2);
+        assert_eq!(lfu.working_set_size(4), 1);
+        assert_eq!(lfu.working_set_size(5), 1);
+        assert_eq!(lfu.working_set_size(6), 0);
+    }
+
+    #[test]
+    fn test_map_values() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("4343"));
+        lfu.get("a");
+        lfu.map_values(|_, value| Bytes::from([&value[..], &value[..]].concat()));
+        assert_eq!(lfu.get_frequency("a"), 1);
+        assert_eq!(lfu.get_frequency("b"), 0);
+        assert_eq!(lfu.get("a"), Some(&Bytes::from("4242")));
+        assert_eq!(lfu.get("b"), Some(&Bytes::from("43434343")));
+    }
+
+    #[test]
+    fn test_update_preserves_frequency() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.get("a");
+        lfu.get("a");
+        assert_eq!(lfu.insert("a".to_string(), Bytes::from("43")), Some(Bytes::from("42")));
+        assert_eq!(lfu.get_frequency("a"), 2);
+        assert_eq!(lfu.working_set_size(0), 1);
+    }
+
+    #[test]
+    fn test_count_writes() {
+        let mut counting = LFU::new().count_writes(true);
+        let mut plain = LFU::new();
+        for lfu in [&mut counting, &mut plain].iter_mut() {
+            lfu.insert("a".to_string(), Bytes::from("42"));
+            lfu.get("a");
+            lfu.insert("a".to_string(), Bytes::from("43"));
+        }
+        assert_eq!(counting.get_frequency("a"), 2);
+        assert_eq!(plain.get_frequency("a"), 1);
+        assert_eq!(counting.get("a"), Some(&Bytes::from("43")));
+        assert_eq!(plain.get("a"), Some(&Bytes::from("43")));
+    }
+
+    #[test]
+    fn test_drain_filter() {
+        let mut lfu = LFU::new();
+        for key in &["a", "b", "c", "d"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        lfu.get("a");
+        lfu.get("c");
+        let cold: Vec<String> = ["a", "b", "c", "d"].iter()
+            .filter(|key| lfu.get_frequency(key) == 0)
+            .map(|key| key.to_string())
+            .collect();
+        // entries are only removed as the iterator is advanced
+        let mut drained: Vec<String> = lfu.drain_filter(|key, _| cold.contains(key)).take(1).map(|(key, _)| key).collect();
+        assert_eq!(lfu.working_set_size(0), 3);
+        drained.extend(lfu.drain_filter(|key, _| cold.contains(key)).map(|(key, _)| key));
+        drained.sort();
+        assert_eq!(drained, vec!["b".to_string(), "d".to_string()]);
+        assert_eq!(lfu.working_set_size(0), 2);
+        assert_eq!(lfu.get_frequency("a"), 1);
+        assert_eq!(lfu.get_frequency("c"), 1);
+        assert_chain(&lfu);
+        // emptying nodes on the way, every key is still seen once
+        for key in &["b", "d", "e"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        lfu.get("e");
+        lfu.get("e");
+        let mut drained: Vec<String> = lfu.drain_filter(|key, _| key != "c").map(|(key, _)| key).collect();
+        drained.sort();
+        assert_eq!(drained, vec!["a", "b", "d", "e"]);
+        assert_eq!(lfu.snapshot_keys(), vec!["c"]);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_nth_coldest_frequency() {
+        let mut lfu = LFU::new();
+        insert_accessed(&mut lfu, &[("a", 0), ("b", 0), ("c", 2), ("d", 2), ("e", 5)]);
+        let expected = [None, Some(0), Some(0), Some(2), Some(2), Some(5), None];
+        for (n, frequency) in expected.iter().enumerate() {
+            assert_eq!(lfu.nth_coldest_frequency(n), *frequency);
+        }
+        assert_eq!(LFU::new().nth_coldest_frequency(1), None);
+    }
+
+    #[test]
+    fn test_snapshot_keys_remove_during_iteration() {
+        let mut lfu = LFU::new();
+        for key in &["a", "b", "c", "d"] {
+            lfu.insert(key.to_string(), Bytes::from(key.to_string()));
+        }
+        let mut snapshot = lfu.snapshot_keys();
+        snapshot.sort();
+        for key in &snapshot {
+            if key == "a" {
+                // removing entries not visited yet is fine as well
+                lfu.remove("c");
+            }
+            if let Some(value) = lfu.get(key).cloned() {
+                assert_eq!(value, Bytes::from(key.clone()));
+                if key == "b" {
+                    lfu.remove(key);
+                }
+            }
+        }
+        let mut remaining = lfu.snapshot_keys();
+        remaining.sort();
+        assert_eq!(remaining, vec!["a".to_string(), "d".to_string()]);
+        assert_eq!(lfu.working_set_size(1), 2);
+    }
+
+    #[test]
+    fn test_empty_value() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::new());
+        assert!(lfu.contains_key("a"));
+        assert_eq!(lfu.len(), 1);
+        assert_eq!(lfu.get("a"), Some(&Bytes::new()));
+        assert_eq!(lfu.get("b"), None);
+        assert_eq!(lfu.get_frequency("a"), 1);
+        assert_eq!(lfu.remove("a"), Some(Bytes::new()));
+        assert!(!lfu.contains_key("a"));
+        assert!(lfu.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "simulation")]
+    fn test_simulate() {
+        let key = |i: usize| format!("key{}", i);
+        let mut trace = vec![];
+        for i in 0..4 {
+            trace.push(Access::Insert(key(i), Bytes::from("42")));
+        }
+        trace.push(Access::Get(key(0)));
+        trace.push(Access::Get(key(0)));
+        trace.push(Access::Get(key(1)));
+        // evicts key2, then key3
+        trace.push(Access::Insert(key(4), Bytes::from("42")));
+        trace.push(Access::Insert(key(5), Bytes::from("42")));
+        trace.push(Access::Insert(key(0), Bytes::from("43")));
+        trace.push(Access::Get(key(2)));
+        trace.push(Access::Get(key(3)));
+        trace.push(Access::Get(key(4)));
+        trace.push(Access::Remove(key(4)));
+        trace.push(Access::Get(key(4)));
+        let mut lfu = LFU::new().max_size(4);
+        let result = lfu.simulate(&trace);
+        assert_eq!(result, SimulationResult { hits: 4, misses: 3, evictions: 2 });
+        assert_eq!(lfu.len(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "simulation")]
+    fn test_simulate_refused_inserts() {
+        let trace = [
+            Access::Insert("a".to_string(), Bytes::from("1")),
+            Access::Insert("toolong".to_string(), Bytes::from("2")),
+            Access::Insert("b".to_string(), Bytes::from("12345")),
+            Access::Insert("a".to_string(), Bytes::from("12345")),
+        ];
+        let mut lfu = LFU::new().max_key_len(3).max_bytes(4);
+        let result = lfu.simulate(&trace);
+        assert_eq!(result.evictions, 0);
+        assert_eq!(lfu.stats().evictions, 0);
+        assert!(lfu.is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let trace = vec![
+            Access::Insert("a".to_string(), Bytes::from("1")),
+            Access::Insert("b".to_string(), Bytes::from("2")),
+            Access::Get("a".to_string()),
+            Access::Get("a".to_string()),
+            Access::Get("missing".to_string()),
+            Access::Insert("b".to_string(), Bytes::from("3")),
+            Access::Remove("c".to_string()),
+            Access::Insert("c".to_string(), Bytes::from("4")),
+            Access::Remove("b".to_string()),
+        ];
+        let mut lfu = LFU::new();
+        for access in trace {
+            lfu.apply(access);
+        }
+        assert_eq!(lfu.len(), 2);
+        assert!(!lfu.contains_key("b"));
+        assert_eq!(lfu.get_frequency("a"), 2);
+        assert_eq!(lfu.get_frequency("c"), 0);
+        assert_eq!(lfu.stats(), CacheStats { hits: 2, misses: 1, evictions: 0, corruptions: 0 });
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut lfu = LFU::new().case_insensitive(true);
+        lfu.insert("Foo".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.get("foo"), Some(&Bytes::from("42")));
+        assert_eq!(lfu.get("FOO"), Some(&Bytes::from("42")));
+        assert!(lfu.contains_key("fOo"));
+        assert_eq!(lfu.get_frequency("Foo"), 2);
+        assert_eq!(lfu.insert("foo".to_string(), Bytes::from("43")), Some(Bytes::from("42")));
+        assert_eq!(lfu.len(), 1);
+        assert_eq!(lfu.snapshot_keys(), vec!["foo".to_string()]);
+        assert_eq!(lfu.remove("FOO"), Some(Bytes::from("43")));
+        assert!(lfu.is_empty());
+
+        let mut lfu = LFU::new();
+        lfu.insert("Foo".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.get("foo"), None);
+    }
+
+    // inserts every key with itself as the value, then gets it the given number of times
+    fn insert_accessed(lfu: &mut LFU, entries: &[(&str, usize)]) {
+        for (key, accesses) in entries {
+            lfu.insert(key.to_string(), Bytes::from(key.to_string()));
+            for _ in 0..*accesses {
+                lfu.get(key);
+            }
+        }
+    }
+
+    // walks the chain checking links, ordering and that only the head is ever empty
+    fn assert_chain(lfu: &LFU) {
+        let nodes: Vec<_> = lfu.nodes().collect();
+        assert_eq!(nodes[0].borrow().value, 0);
+        assert!(nodes[0].borrow().prev.is_none());
+        assert!(Rc::ptr_eq(nodes.last().unwrap(), &lfu.frequency_tail));
+        for pair in nodes.windows(2) {
+            let (prev, next) = (pair[0].borrow(), pair[1].borrow());
+            assert!(prev.value < next.value);
+            assert!(!next.items.is_empty());
+            assert!(Rc::ptr_eq(&next.prev.as_ref().unwrap().upgrade().unwrap(), &pair[0]));
+        }
+        let mut total = 0;
+        for node in &nodes {
+            for key in &node.borrow().items {
+                assert!(Rc::ptr_eq(&lfu.items[key.as_str()].parent, node));
+                total += 1;
+            }
+        }
+        assert_eq!(total, lfu.len());
+    }
+
+    #[test]
+    fn test_coldest_hottest() {
+        let mut lfu = LFU::new();
+        for key in &["a", "b", "c", "d"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        for key in &["b", "b", "b", "c", "c", "a", "d", "d", "d"] {
+            lfu.get(key);
+        }
+        assert_chain(&lfu);
+        assert_eq!(lfu.coldest(), Some((&"a".to_string(), 1)));
+        assert_eq!(lfu.hottest(), Some((&"b".to_string(), 3)));
+        lfu.remove("b");
+        assert_chain(&lfu);
+        assert_eq!(lfu.hottest(), Some((&"d".to_string(), 3)));
+        lfu.remove("d");
+        assert_chain(&lfu);
+        assert_eq!(lfu.hottest(), Some((&"c".to_string(), 2)));
+        lfu.insert("e".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.coldest(), Some((&"e".to_string(), 0)));
+        lfu.tick();
+        assert_chain(&lfu);
+        // a joins the head behind e
+        assert_eq!(lfu.coldest(), Some((&"e".to_string(), 0)));
+        assert_eq!(lfu.working_set_size(1), 1);
+        assert_eq!(lfu.hottest(), Some((&"c".to_string(), 1)));
+    }
+
+    #[test]
+    fn test_sparse_chain() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        for _ in 0..5 {
+            lfu.get("a");
+        }
+        assert_chain(&lfu);
+        assert_eq!(lfu.nodes().count(), 2);
+        // b has to get its own nodes between the head and a
+        for frequency in 1..8 {
+            lfu.get("b");
+            assert_chain(&lfu);
+            assert_eq!(lfu.get_frequency("b"), frequency);
+        }
+        assert_eq!(lfu.get_frequency("a"), 5);
+        assert_eq!(lfu.hottest(), Some((&"b".to_string(), 7)));
+    }
+
+    #[test]
+    fn test_overhead_bytes() {
+        let fill = |count: usize, value: &'static str| {
+            let mut lfu = LFU::new().max_size(count);
+            for i in 0..count {
+                lfu.insert(format!("key{}", i), Bytes::from(value));
+            }
+            lfu
+        };
+        let small = fill(10, "42").overhead_bytes();
+        let large = fill(1000, "42").overhead_bytes();
+        assert!(large > 50 * small);
+        // values don't count as overhead
+        assert_eq!(fill(1000, "4242424242424242").overhead_bytes(), large);
+    }
+
+    #[test]
+    fn test_huge_max_size() {
+        let mut lfu = LFU::new().max_size(usize::MAX);
+        assert!(lfu.items.capacity() < 1024);
+        for i in 0..1000 {
+            lfu.insert(format!("key{}", i), Bytes::from("42"));
+        }
+        assert_eq!(lfu.len(), 1000);
+        assert!(lfu.items.capacity() < 4096);
+    }
+
+    #[test]
+    fn test_entries() {
+        let mut lfu = LFU::new();
+        let before = Instant::now();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        let inserted = lfu.entries().find(|entry| entry.key == "a").unwrap().last_access;
+        assert!(inserted >= before);
+        for _ in 0..3 {
+            lfu.get("a");
+        }
+        lfu.tick();
+        let entry = lfu.entries().find(|entry| entry.key == "a").unwrap();
+        assert_eq!(entry.value, &Bytes::from("42"));
+        assert_eq!(entry.frequency, 2);
+        assert_eq!(entry.accesses, 3);
+        assert!(entry.last_access >= inserted);
+        let entry = lfu.entries().find(|entry| entry.key == "b").unwrap();
+        assert_eq!((entry.frequency, entry.accesses), (0, 0));
+        assert_eq!(lfu.entries().count(), 2);
+    }
+
+    #[test]
+    fn test_was_recently_evicted() {
+        let mut lfu = LFU::new().max_size(2).ghost_size(2);
+        for key in &["a", "b", "c", "d", "e"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        // a got pushed out of the history by b and c
+        assert!(!lfu.was_recently_evicted("a"));
+        assert!(lfu.was_recently_evicted("b"));
+        assert!(lfu.was_recently_evicted("c"));
+        assert!(!lfu.was_recently_evicted("d"));
+        lfu.remove("d");
+        assert!(!lfu.was_recently_evicted("d"));
+        lfu.insert("b".to_string(), Bytes::from("42"));
+        assert!(!lfu.was_recently_evicted("b"));
+
+        let mut lfu = LFU::new().max_size(1);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("42"));
+        assert!(!lfu.was_recently_evicted("a"));
+
+        // the stale slot of a re-inserted key doesn't push out its newer one
+        let mut lfu = LFU::new().max_size(1).ghost_size(2);
+        for key in &["a", "b", "a", "c", "d"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        assert!(lfu.was_recently_evicted("a"));
+        assert!(!lfu.was_recently_evicted("b"));
+        assert!(lfu.was_recently_evicted("c"));
+        // keys cycling through the cache leave stale slots, which get compacted
+        for _ in 0..100 {
+            lfu.insert("a".to_string(), Bytes::from("42"));
+            lfu.insert("b".to_string(), Bytes::from("42"));
+        }
+        assert!(lfu.ghosts.queue.len() <= 4);
+        assert!(lfu.was_recently_evicted("a"));
+    }
+
+    #[test]
+    fn test_keys_stored_once() {
+        let overhead = |key_len: usize| {
+            let mut lfu = LFU::new().max_size(100);
+            for i in 0..100 {
+                lfu.insert(format!("{:0width$}", i, width = key_len), Bytes::from("42"));
+            }
+            lfu.get(&format!("{:0width$}", 0, width = key_len));
+            lfu.overhead_bytes()
+        };
+        // every key grew by 1000 bytes, storing it twice would add 200_000
+        let extra = overhead(1010) - overhead(10);
+        assert!((100_000..150_000).contains(&extra));
+
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.get("a");
+        let node = lfu.items["a"].parent.clone();
+        assert_eq!(Rc::strong_count(&node.borrow().items[0]), 2);
+        assert_eq!(lfu.remove_entry("a"), Some(("a".to_string(), Bytes::from("42"))));
+    }
+
+    #[test]
+    fn test_recompute_size() {
+        let mut lfu = LFU::new().max_size(3);
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        lfu.current_size = 1;
+        lfu.recompute_size();
+        assert_eq!(lfu.len(), 3);
+        // a correct counter means inserts evict again instead of growing past max_size
+        lfu.insert("d".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.len(), 3);
+        assert_eq!(lfu.items.len(), 3);
+    }
+
+    #[test]
+    fn test_promote_after() {
+        let mut lfu = LFU::new().max_size(2).promote_after(3);
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.get("a");
+        lfu.get("a");
+        assert_eq!(lfu.get_frequency("a"), 0);
+        // still the oldest frequency 0 entry, so the scanned key goes first
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        assert!(!lfu.contains_key("a"));
+
+        lfu.get("b");
+        lfu.get("b");
+        lfu.get("b");
+        assert_eq!(lfu.get_frequency("b"), 1);
+        // once promoted every access counts
+        lfu.get("b");
+        assert_eq!(lfu.get_frequency("b"), 2);
+        // aging back down starts a new probation
+        lfu.tick();
+        lfu.tick();
+        lfu.get("b");
+        assert_eq!(lfu.get_frequency("b"), 0);
+        assert_eq!(lfu.entries().find(|entry| entry.key == "b").unwrap().accesses, 5);
+    }
+
+    #[test]
+    fn test_max_key_bytes() {
+        let key = |c: char| std::iter::repeat_n(c, 100).collect::<String>();
+        let mut lfu = LFU::new().max_key_bytes(250);
+        lfu.insert(key('a'), Bytes::from("1"));
+        lfu.insert(key('b'), Bytes::from("2"));
+        lfu.get(&key('a'));
+        lfu.insert(key('c'), Bytes::from("3"));
+        assert_eq!(lfu.len(), 2);
+        assert!(!lfu.contains_key(&key('b')));
+        assert_eq!(lfu.key_bytes, 200);
+        // short keys fit next to the long ones
+        lfu.insert("d".to_string(), Bytes::from("4"));
+        assert_eq!(lfu.len(), 3);
+        lfu.remove(&key('c'));
+        assert_eq!(lfu.key_bytes, 101);
+        // too long to ever fit
+        assert_eq!(lfu.insert(std::iter::repeat_n('e', 251).collect(), Bytes::from("5")), None);
+        assert_eq!(lfu.len(), 2);
+        lfu.key_bytes = 0;
+        lfu.recompute_size();
+        assert_eq!(lfu.key_bytes, 101);
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut source = LFU::new();
+        let mut dest = LFU::new().max_size(2);
+        source.insert("hot".to_string(), Bytes::from("42"));
+        for _ in 0..5 {
+            source.get("hot");
+        }
+        dest.insert("a".to_string(), Bytes::from("43"));
+        dest.insert("b".to_string(), Bytes::from("44"));
+        for _ in 0..7 {
+            dest.get("b");
+        }
+        assert!(source.transfer("hot", &mut dest));
+        assert!(!source.transfer("hot", &mut dest));
+        assert!(source.is_empty());
+        assert_chain(&source);
+        // dest made room as for any insert
+        assert!(!dest.contains_key("a"));
+        assert_eq!(dest.get_frequency("hot"), 5);
+        assert_eq!(dest.get_frequency("b"), 7);
+        assert_chain(&dest);
+        assert_eq!(dest.get("hot"), Some(&Bytes::from("42")));
+        assert_eq!(dest.get_frequency("hot"), 6);
+        assert_chain(&dest);
+        assert!(dest.transfer("b", &mut source));
+        assert_eq!(source.hottest(), Some((&"b".to_string(), 7)));
+        assert_chain(&source);
+    }
+
+    #[test]
+    fn test_transfer_refused() {
+        let mut source = LFU::new();
+        source.insert("long".to_string(), Bytes::from("42"));
+        source.get("long");
+        for mut dest in [LFU::new().max_key_len(1), LFU::new().max_key_bytes(3), LFU::new().max_bytes(1), LFU::new().max_size(0)] {
+            assert!(!source.transfer("long", &mut dest));
+            assert!(dest.is_empty());
+            assert_eq!(source.get_frequency("long"), 1);
+        }
+        assert_chain(&source);
+        // making room would take a protected entry, x stays and nothing is evicted
+        let clock = ManualClock::new();
+        let mut dest = LFU::new().max_bytes(10).clock(clock.clone()).min_residency(Duration::from_secs(60));
+        dest.insert("w".to_string(), Bytes::from("12345"));
+        clock.advance(Duration::from_secs(60));
+        dest.insert("x".to_string(), Bytes::from("12345"));
+        source.insert("y".to_string(), Bytes::from("12345678"));
+        assert!(!source.transfer("y", &mut dest));
+        assert!(dest.contains_key("w"));
+        assert!(dest.contains_key("x"));
+        assert_eq!(dest.stats().evictions, 0);
+        assert!(source.contains_key("y"));
+        assert_chain(&dest);
+    }
+
+    #[test]
+    fn test_transfer_keeps_counted_write() {
+        let mut source = LFU::new();
+        let mut dest = LFU::new().count_writes(true);
+        source.insert("a".to_string(), Bytes::from("1"));
+        source.get("a");
+        dest.insert("a".to_string(), Bytes::from("2"));
+        dest.get("a");
+        dest.get("a");
+        assert!(source.transfer("a", &mut dest));
+        assert_eq!(dest.get_frequency("a"), 3);
+        assert_chain(&dest);
+    }
+
+    #[test]
+    fn test_transfer_existing_key() {
+        let mut source = LFU::new();
+        let mut dest = LFU::new();
+        source.insert("a".to_string(), Bytes::from("1"));
+        dest.insert("a".to_string(), Bytes::from("2"));
+        for _ in 0..5 {
+            dest.get("a");
+        }
+        // the value comes from source, the frequency is the higher one
+        assert!(source.transfer("a", &mut dest));
+        assert_eq!(dest.get_frequency("a"), 5);
+        source.insert("a".to_string(), Bytes::from("3"));
+        for _ in 0..8 {
+            source.get("a");
+        }
+        assert!(source.transfer("a", &mut dest));
+        assert_eq!(dest.get_frequency("a"), 8);
+        assert_eq!(dest.len(), 1);
+        assert_chain(&dest);
+        assert_eq!(dest.get("a"), Some(&Bytes::from("3")));
+    }
+
+    #[test]
+    fn test_score() {
+        let mut lfu = LFU::new();
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        assert_eq!(lfu.score("a"), Some(1.0));
+        for _ in 0..4 {
+            lfu.get("a");
+        }
+        lfu.get("b");
+        assert_eq!(lfu.score("a"), Some(1.0));
+        assert_eq!(lfu.score("b"), Some(0.25));
+        assert_eq!(lfu.score("c"), Some(0.0));
+        assert_eq!(lfu.score("d"), None);
+        lfu.remove("a");
+        assert_eq!(lfu.score("b"), Some(1.0));
+    }
+
+    #[test]
+    fn test_touch_many() {
+        let mut lfu = LFU::new();
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        assert_eq!(lfu.touch_many(&["a", "x", "b", "a", "y"]), 3);
+        assert_eq!(lfu.get_frequency("a"), 2);
+        assert_eq!(lfu.get_frequency("b"), 1);
+        assert_eq!(lfu.get_frequency("c"), 0);
+        assert!(!lfu.contains_key("x"));
+        assert_eq!(lfu.touch_many(&[]), 0);
+    }
+
+    #[test]
+    fn test_min_residency() {
+        let clock = ManualClock::new();
+        let mut lfu = LFU::new().max_size(2).min_residency(Duration::from_secs(60)).clock(clock.clone());
+        lfu.insert("a".to_string(), Bytes::from("42"));
+        clock.advance(Duration::from_secs(60));
+        lfu.insert("b".to_string(), Bytes::from("43"));
+        lfu.get("a");
+        // b is colder but too young, so the hotter but older a goes
+        lfu.insert("c".to_string(), Bytes::from("44"));
+        assert!(!lfu.contains_key("a"));
+        assert!(lfu.contains_key("b"));
+        // nothing is old enough, the new key is refused rather than going over capacity
+        assert_eq!(lfu.insert_reporting("d".to_string(), Bytes::from("45")), (None, vec![]));
+        assert!(!lfu.contains_key("d"));
+        assert_eq!(lfu.len(), 2);
+        // updates still work
+        lfu.insert("c".to_string(), Bytes::from("46"));
+        assert_eq!(lfu.get("c"), Some(&Bytes::from("46")));
+        clock.advance(Duration::from_secs(59));
+        lfu.insert("e".to_string(), Bytes::from("47"));
+        assert!(!lfu.contains_key("e"));
+        clock.advance(Duration::from_secs(1));
+        lfu.insert("e".to_string(), Bytes::from("47"));
+        assert!(lfu.contains_key("e"));
+        assert!(!lfu.contains_key("b"));
+        assert_eq!(lfu.len(), 2);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_min_residency_flood() {
+        let mut lfu = LFU::new().max_size(2).min_residency(Duration::from_secs(60));
+        for i in 0..10_000 {
+            lfu.insert(i.to_string(), Bytes::from("42"));
+        }
+        assert_eq!(lfu.len(), 2);
+        assert!(lfu.contains_key("0"));
+        assert!(lfu.contains_key("1"));
+    }
+
+    #[test]
+    fn test_min_residency_refuses_before_evicting() {
+        let clock = ManualClock::new();
+        let mut lfu = LFU::new().max_bytes(10).min_residency(Duration::from_secs(60)).clock(clock.clone());
+        lfu.insert("w".to_string(), Bytes::from("12345"));
+        clock.advance(Duration::from_secs(60));
+        lfu.insert("x".to_string(), Bytes::from("12345"));
+        // room for y would take w and the protected x, so w stays
+        assert_eq!(lfu.insert_reporting("y".to_string(), Bytes::from("12345678")), (None, vec![]));
+        assert!(lfu.contains_key("w"));
+        assert!(lfu.contains_key("x"));
+        assert!(!lfu.contains_key("y"));
+        assert_eq!(lfu.stats().evictions, 0);
+        // what fits after evicting w alone still goes in
+        assert_eq!(lfu.insert_reporting("y".to_string(), Bytes::from("1234")).1.len(), 1);
+        assert!(lfu.contains_key("y"));
+        assert_chain(&lfu);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    extern crate quickcheck;
-
-    use crate::*;
-    use bytes::Bytes;
 
     #[test]
-    fn it_works() {
+    fn test_transfer_dest_full_of_young_entries() {
+        let mut source = LFU::new();
+        source.insert("a".to_string(), Bytes::from("42"));
+        source.get("a");
+        let mut dest = LFU::new().max_size(1).min_residency(Duration::from_secs(60)).clock(ManualClock::new());
+        dest.insert("b".to_string(), Bytes::from("43"));
+        // dest has nothing it may evict, the entry stays in source
+        assert!(!source.transfer("a", &mut dest));
+        assert_eq!(source.get_frequency("a"), 1);
+        assert_eq!(source.get("a"), Some(&Bytes::from("42")));
+        assert!(!dest.contains_key("a"));
+        assert_chain(&source);
+        assert_chain(&dest);
+    }
+
+    #[test]
+    fn test_contains_value() {
         let mut lfu = LFU::new();
+        assert!(!lfu.contains_value(&Bytes::new()));
         lfu.insert("a".to_string(), Bytes::from("42"));
-        assert_eq!(lfu.get(&"a".to_string()), Some(&Bytes::from("42")));
-        print!("{:?}", lfu);
+        lfu.insert("b".to_string(), Bytes::new());
+        assert!(lfu.contains_value(&Bytes::from("42")));
+        assert!(lfu.contains_value(&Bytes::new()));
+        assert!(!lfu.contains_value(&Bytes::from("43")));
+        lfu.remove("a");
+        assert!(!lfu.contains_value(&Bytes::from("42")));
+        assert_eq!(lfu.get_frequency("b"), 0);
     }
+
     #[test]
-    fn test_max_size() {
-        let lfu = LFU::new().max_size(1000);
-        assert_eq!(lfu.max_size, 1000);
+    fn test_shrink_to_fit() {
+        let mut lfu = LFU::new().max_size(usize::MAX);
+        for i in 0..1000 {
+            lfu.insert(format!("key{}", i), Bytes::from("42"));
+        }
+        let peak = lfu.overhead_bytes();
+        for i in 10..1000 {
+            lfu.remove(&format!("key{}", i));
+        }
+        assert!(lfu.overhead_bytes() > peak / 2);
+        lfu.shrink_to_fit();
+        assert!(lfu.overhead_bytes() < peak / 10);
+        assert!(lfu.frequency_head.borrow().items.capacity() < 100);
+        assert_eq!(lfu.len(), 10);
+        assert_eq!(lfu.get("key0"), Some(&Bytes::from("42")));
     }
 
     #[test]
-    fn test_evictions() {
-        let mut lfu = LFU::new().max_size(3);
-        lfu.insert("a".to_string(), Bytes::from("42"));
-        lfu.insert("b".to_string(), Bytes::from("43"));
-        println!("{:?}", lfu);
+    fn test_survivors_if_shrunk_to() {
+        let mut lfu = LFU::new();
+        insert_accessed(&mut lfu, &[("key0", 3), ("key1", 0), ("key2", 1), ("key3", 0), ("key4", 5), ("key5", 1), ("key6", 2)]);
+        assert_eq!(lfu.survivors_if_shrunk_to(10).len(), 7);
+        assert!(lfu.survivors_if_shrunk_to(0).is_empty());
+        let preview: Vec<String> = lfu.survivors_if_shrunk_to(3).into_iter().cloned().collect();
+        assert_eq!(preview, vec!["key6", "key0", "key4"]);
+        assert_eq!(lfu.len(), 7);
+        lfu.shrink_to(3);
+        let mut survivors = lfu.snapshot_keys();
+        survivors.sort();
+        let mut preview = preview;
+        preview.sort();
+        assert_eq!(survivors, preview);
+        assert_chain(&lfu);
+        lfu.insert("key7".to_string(), Bytes::from("42"));
+        assert_eq!(lfu.len(), 3);
     }
 
     #[test]
-    fn test_frequency() {
-        let mut lfu = LFU::new().max_size(3);
-        lfu.insert("a".to_string(), Bytes::from("42"));
+    fn test_reader() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("0123456789"));
+        assert!(lfu.reader("b").is_none());
+        let mut chunks = vec![];
+        {
+            let mut reader = lfu.reader("a").unwrap();
+            let mut chunk = [0; 4];
+            loop {
+                let read = reader.read(&mut chunk).unwrap();
+                if read == 0 {
+                    break
+                }
+                chunks.push(chunk[..read].to_vec());
+            }
+        }
+        assert_eq!(chunks, vec![b"0123".to_vec(), b"4567".to_vec(), b"89".to_vec()]);
+        assert_eq!(lfu.get_frequency("a"), 1);
+    }
+
+    #[test]
+    fn test_no_promote_above_bytes() {
+        let mut lfu = LFU::new().no_promote_above_bytes(4);
+        lfu.insert("large".to_string(), Bytes::from("0123456789"));
+        lfu.insert("small".to_string(), Bytes::from("0123"));
+        for _ in 0..3 {
+            lfu.get("large");
+            lfu.get("small");
+        }
+        assert_eq!(lfu.get_frequency("large"), 0);
+        assert_eq!(lfu.get_frequency("small"), 3);
+        assert_eq!(lfu.entries().find(|entry| entry.key == "large").unwrap().accesses, 3);
+        // shrinking the value makes it promotable again
+        lfu.insert("large".to_string(), Bytes::from("01"));
+        lfu.get("large");
+        assert_eq!(lfu.get_frequency("large"), 1);
+    }
+
+    #[test]
+    fn test_insert_reporting() {
+        let mut lfu = LFU::new().max_size(2);
+        assert_eq!(lfu.insert_reporting("a".to_string(), Bytes::from("42")), (None, vec![]));
+        assert_eq!(lfu.insert_reporting("b".to_string(), Bytes::from("43")), (None, vec![]));
+        lfu.get("a");
+        // replacing a key evicts nothing
+        assert_eq!(lfu.insert_reporting("a".to_string(), Bytes::from("44")), (Some(Bytes::from("42")), vec![]));
+        // a new key pushes out the coldest one
+        assert_eq!(
+            lfu.insert_reporting("c".to_string(), Bytes::from("45")),
+            (None, vec![("b".to_string(), Bytes::from("43"))])
+        );
+
+        let mut lfu = LFU::new().max_key_bytes(4);
+        lfu.insert("ab".to_string(), Bytes::from("42"));
+        lfu.insert("cd".to_string(), Bytes::from("43"));
+        let (previous, evicted) = lfu.insert_reporting("efgh".to_string(), Bytes::from("44"));
+        assert_eq!(previous, None);
+        assert_eq!(evicted.len(), 2);
+    }
+
+    #[test]
+    fn test_stats_clear_if() {
+        let mut lfu = LFU::new().max_size(2).ghost_size(4);
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        lfu.get("c");
+        for key in &["a", "d", "e", "f"] {
+            lfu.get(key);
+        }
+        assert_eq!(lfu.stats(), CacheStats { hits: 1, misses: 4, evictions: 1, corruptions: 0 });
+        assert!(!lfu.clear_if(|stats| stats.hit_ratio() < 0.1));
+        assert_eq!(lfu.len(), 2);
+        assert!(lfu.clear_if(|stats| stats.hit_ratio() < 0.25));
+        assert!(lfu.is_empty());
+        assert_eq!(lfu.stats(), CacheStats::default());
+        assert!(!lfu.was_recently_evicted("a"));
+        assert_chain(&lfu);
+        // configuration is kept
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("42"));
+        }
+        assert_eq!(lfu.len(), 2);
+        assert_eq!(lfu.key_bytes, 2);
+    }
+
+    #[test]
+    fn test_bytes_by_frequency() {
+        let mut lfu = LFU::new();
+        assert!(lfu.bytes_by_frequency().is_empty());
+        let entries = [("a", 100, 0), ("b", 50, 0), ("c", 10, 2), ("d", 5, 2), ("e", 1, 4)];
+        for (key, len, accesses) in &entries {
+            lfu.insert(key.to_string(), Bytes::from(vec![0; *len]));
+            for _ in 0..*accesses {
+                lfu.get(key);
+            }
+        }
+        let expected: BTreeMap<u32, usize> = vec![(0, 150), (2, 15), (4, 1)].into_iter().collect();
+        assert_eq!(lfu.bytes_by_frequency(), expected);
+        lfu.remove("a");
+        lfu.remove("b");
+        // the emptied head bucket doesn't show up
+        assert_eq!(lfu.bytes_by_frequency().keys().cloned().collect::<Vec<_>>(), vec![2, 4]);
+    }
+
+    #[test]
+    fn test_promote_boundaries() {
+        // a lone key is both the only item of its node and the tail, every promotion empties
+        // the node it leaves and has to splice in its own successor
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        for frequency in 1..5 {
+            lfu.get("a");
+            assert_eq!(lfu.get_frequency("a"), frequency);
+            assert_chain(&lfu);
+            assert_eq!(lfu.nodes().count(), 2);
+        }
+        // a key catching up with its neighbour moves into the existing node and unlinks its own
+        lfu.insert("b".to_string(), Bytes::from("2"));
+        for _ in 0..4 {
+            lfu.get("b");
+            assert_chain(&lfu);
+        }
+        assert_eq!(lfu.nodes().count(), 2);
+        assert_eq!(lfu.get_frequency("b"), 4);
+        // and overtaking it leaves the other key as the sole item between head and tail
+        lfu.get("b");
+        assert_chain(&lfu);
+        assert_eq!(lfu.nodes().map(|node| node.borrow().value).collect::<Vec<_>>(), vec![0, 4, 5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "frequency node linked as its own next")]
+    fn test_promote_self_linked_node() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("1"));
         lfu.get("a");
+        // corrupt the chain on purpose, the node after the head points back at itself
+        let node = lfu.frequency_tail.clone();
+        node.borrow_mut().next = Some(node.clone());
         lfu.get("a");
-        assert_eq!(lfu.get_frequency("a"), 2);
-        println!("{:?}", lfu);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut lfu = LFU::new();
+        insert_accessed(&mut lfu, &[("a", 0), ("b", 1), ("c", 3)]);
+        for (key, value) in lfu.iter_mut() {
+            *value = Bytes::from(format!("{}{}", key, key));
+        }
+        assert_chain(&lfu);
+        assert_eq!(lfu.get_frequency("a"), 0);
+        assert_eq!(lfu.get_frequency("b"), 1);
+        assert_eq!(lfu.get_frequency("c"), 3);
+        assert!(lfu.entries().all(|entry| entry.value.len() == 2));
+        assert_eq!(lfu.stats().hits, 4);
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut lfu = LFU::builder().max_size(2).build();
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("1"));
+        }
+        assert_eq!(lfu.len(), 2);
+        assert!(!lfu.contains_key("a"));
+        assert_eq!(LFU::builder().unbounded().build().max_size, usize::MAX);
+    }
+
+    #[test]
+    fn test_chain_summary() {
+        let mut lfu = LFU::new();
+        assert!(lfu.chain_summary().is_empty());
+        insert_accessed(&mut lfu, &[("a", 0), ("b", 1), ("c", 1), ("d", 3), ("e", 5)]);
+        assert_eq!(lfu.chain_summary(), vec![(0, 1), (1, 2), (3, 1), (5, 1)]);
+        // emptied buckets are unlinked, and the empty head is skipped
+        lfu.remove("a");
+        lfu.remove("d");
+        lfu.get("e");
+        assert_eq!(lfu.chain_summary(), vec![(1, 2), (6, 1)]);
+    }
+
+    #[test]
+    fn test_insert_if_better() {
+        let mut lfu = LFU::new().max_size(2);
+        // room to spare admits regardless of the estimate
+        assert!(lfu.insert_if_better("a".to_string(), Bytes::from("1"), 0));
+        assert!(lfu.insert_if_better("b".to_string(), Bytes::from("2"), 0));
+        lfu.get("a");
+        lfu.get("a");
+        lfu.get("b");
+        // the victim is b at frequency 1, an equal estimate declines and leaves the cache as is
+        assert!(!lfu.insert_if_better("c".to_string(), Bytes::from("3"), 1));
+        assert_eq!(lfu.len(), 2);
+        assert!(lfu.contains_key("b"));
+        assert_eq!(lfu.stats().evictions, 0);
+        // a hotter estimate admits, evicting exactly the victim
+        assert!(lfu.insert_if_better("c".to_string(), Bytes::from("3"), 2));
+        assert!(!lfu.contains_key("b"));
+        assert!(lfu.contains_key("a"));
+        assert_eq!(lfu.get_frequency("c"), 0);
+        // updates of present keys always go through
+        assert!(lfu.insert_if_better("a".to_string(), Bytes::from("4"), 0));
+        assert_eq!(lfu.get("a"), Some(&Bytes::from("4")));
+        assert_chain(&lfu);
+        // making room under max_bytes takes both entries, the hot second one declines
+        let mut lfu = LFU::new().max_bytes(10);
+        lfu.insert("a".to_string(), Bytes::from("12345"));
+        lfu.insert("b".to_string(), Bytes::from("12345"));
+        for _ in 0..10 {
+            lfu.get("b");
+        }
+        assert!(!lfu.insert_if_better("c".to_string(), Bytes::from("12345678"), 1));
+        assert!(lfu.contains_key("a"));
+        assert!(lfu.contains_key("b"));
+        assert!(lfu.insert_if_better("c".to_string(), Bytes::from("12345678"), 11));
+        assert_eq!(lfu.len(), 1);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_most_common() {
+        let mut lfu = LFU::new();
+        insert_accessed(&mut lfu, &[("a", 1), ("b", 3), ("c", 0), ("d", 3), ("e", 1), ("f", 2)]);
+        let expected = vec![("b", 3), ("d", 3), ("f", 2), ("a", 1), ("e", 1), ("c", 0)];
+        let expected: Vec<_> = expected.into_iter().map(|(key, frequency)| (key.to_string(), frequency)).collect();
+        assert_eq!(lfu.most_common(10), expected);
+        assert_eq!(lfu.most_common(3), expected[..3].to_vec());
+        assert_eq!(lfu.most_common(1), expected[..1].to_vec());
+        assert!(lfu.most_common(0).is_empty());
+        assert!(LFU::new().most_common(1).is_empty());
+    }
+
+    #[test]
+    fn test_gdsf_max_frequency() {
+        let entries = vec![("a".to_string(), Bytes::from("1"), u32::MAX)];
+        let mut lfu = LFU::from_frequencies(entries, 1).eviction(EvictionPolicy::GDSF);
+        lfu.insert("b".to_string(), Bytes::from("2"));
+        assert!(lfu.contains_key("b"));
+        assert!(!lfu.contains_key("a"));
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_gdsf() {
+        let large = Bytes::from(vec![0; 1000]);
+        let small = Bytes::from(vec![0; 10]);
+        let fill = |lfu: &mut LFU| {
+            lfu.insert("large".to_string(), large.clone());
+            lfu.insert("small".to_string(), small.clone());
+            for _ in 0..5 {
+                lfu.get("large");
+            }
+            for _ in 0..2 {
+                lfu.get("small");
+            }
+            lfu.insert("new".to_string(), Bytes::from("1"));
+        };
+        // plain LFU keeps the more frequent large value
+        let mut lfu = LFU::new().max_size(2);
+        fill(&mut lfu);
+        assert!(lfu.contains_key("large"));
+        assert!(!lfu.contains_key("small"));
+        // GDSF weighs 6/1000 against 3/10 and lets the large value go
+        let mut lfu = LFU::new().max_size(2).eviction(EvictionPolicy::GDSF);
+        fill(&mut lfu);
+        assert!(!lfu.contains_key("large"));
+        assert!(lfu.contains_key("small"));
+        assert_chain(&lfu);
+        // the clock advanced to the victim's priority, newcomers start from it
+        assert_eq!(lfu.clock, 6.0 / 1000.0);
+        assert_eq!(lfu.priority("new", Instant::now()), 6.0 / 1000.0 + 1.0);
+        assert_eq!(lfu.eviction_order().map(|key| key.to_string()).collect::<Vec<_>>(), vec!["small", "new"]);
+        lfu.clear();
+        assert_eq!(lfu.clock, 0.0);
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let mut lfu = LFU::new().max_size(3).ghost_size(2);
+        for key in &["a", "b", "c", "e"] {
+            lfu.insert(key.to_string(), Bytes::from("1"));
+            lfu.get(key);
+        }
+        lfu.replace_all(vec![("b".to_string(), Bytes::from("2")), ("d".to_string(), Bytes::from("3"))]);
+        assert_eq!(lfu.snapshot_keys().len(), 2);
+        assert_eq!(lfu.chain_summary(), vec![(0, 2)]);
+        assert!(!lfu.contains_key("a"));
+        assert!(!lfu.contains_key("c"));
+        assert_eq!(lfu.get_frequency("b"), 0);
+        assert_eq!(lfu.get_frequency("d"), 0);
+        assert_eq!(lfu.get("b"), Some(&Bytes::from("2")));
+        // stats and the eviction history aren't reset, replaced entries aren't evictions
+        assert_eq!(lfu.stats(), CacheStats { hits: 5, misses: 0, evictions: 1, corruptions: 0 });
+        assert!(lfu.was_recently_evicted("a"));
+        assert!(!lfu.was_recently_evicted("c"));
+        // capacity is kept
+        lfu.replace_all((0..5).map(|i| (i.to_string(), Bytes::from("4"))));
+        assert_eq!(lfu.len(), 3);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_get_capped() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        for expected in &[1, 2, 3, 3, 3] {
+            assert_eq!(lfu.get_capped("a", 3), Some(&Bytes::from("1")));
+            assert_eq!(lfu.get_frequency("a"), *expected);
+        }
+        assert_eq!(lfu.stats().hits, 5);
+        assert_eq!(lfu.entries().next().unwrap().accesses, 5);
+        // a lower ceiling doesn't take earned frequency away, plain gets keep promoting
+        lfu.get_capped("a", 1);
+        assert_eq!(lfu.get_frequency("a"), 3);
+        lfu.get("a");
+        assert_eq!(lfu.get_frequency("a"), 4);
+        // a ceiling of 0 never promotes, not even after probation
+        let mut lfu = LFU::new().promote_after(2);
+        lfu.insert("b".to_string(), Bytes::from("2"));
+        lfu.get_capped("b", 0);
+        lfu.get_capped("b", 0);
+        assert_eq!(lfu.get_frequency("b"), 0);
+        lfu.get("b");
+        assert_eq!(lfu.get_frequency("b"), 0);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_get_frequency_doesnt_walk() {
+        let mut lfu = LFU::new();
+        // one key promoted many times, with more keys spread over the frequencies below it
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        for _ in 0..1000 {
+            lfu.get("a");
+        }
+        for frequency in 1..=10 {
+            lfu.insert(frequency.to_string(), Bytes::from("1"));
+            lfu.place_at_frequency(&frequency.to_string(), frequency * 100 - 1);
+        }
+        assert_eq!(lfu.nodes().count(), 12);
+        NODES_WALKED.with(|walked| walked.set(0));
+        assert_eq!(lfu.get_frequency("a"), 1000);
+        assert_eq!(lfu.get_frequency("5"), 499);
+        assert_eq!(lfu.get_frequency("missing"), 0);
+        assert_eq!(NODES_WALKED.with(|walked| walked.get()), 0);
+        // the counter does see walks
+        lfu.chain_summary();
+        assert_eq!(NODES_WALKED.with(|walked| walked.get()), 12);
+    }
+
+    #[test]
+    fn test_reigning_hot_key() {
+        let clock = ManualClock::new();
+        let mut lfu = LFU::new().clock(clock.clone());
+        assert_eq!(lfu.reigning_hot_key(), None);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.insert("b".to_string(), Bytes::from("2"));
+        lfu.get("a");
+        lfu.get("a");
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(lfu.reigning_hot_key(), Some((&"a".to_string(), 2, Duration::from_secs(10))));
+        // catching up with a isn't enough, a keeps reigning and its time keeps running
+        lfu.get("b");
+        lfu.get("b");
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(lfu.reigning_hot_key(), Some((&"a".to_string(), 2, Duration::from_secs(15))));
+        // overtaking starts a new reign
+        lfu.get("b");
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(lfu.reigning_hot_key(), Some((&"b".to_string(), 3, Duration::from_secs(3))));
+        // a key growing while on top keeps its reign
+        lfu.get("b");
+        assert_eq!(lfu.reigning_hot_key(), Some((&"b".to_string(), 4, Duration::from_secs(3))));
+        // removing the top key hands the spot on straight away
+        lfu.remove("b");
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(lfu.reigning_hot_key(), Some((&"a".to_string(), 2, Duration::from_secs(1))));
+        lfu.clear();
+        assert_eq!(lfu.reigning_hot_key(), None);
+
+        // under LfuThenFifo ties are kept in insertion order, a tying key moves in front of the
+        // reigning one without taking the spot over
+        let mut lfu = LFU::new().clock(clock.clone()).eviction(EvictionPolicy::LfuThenFifo);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.insert("b".to_string(), Bytes::from("2"));
+        lfu.get("b");
+        clock.advance(Duration::from_secs(5));
+        lfu.get("a");
+        assert_eq!(lfu.hottest(), Some((&"a".to_string(), 1)));
+        assert_eq!(lfu.reigning_hot_key(), Some((&"b".to_string(), 1, Duration::from_secs(5))));
+        lfu.insert("c".to_string(), Bytes::from("3"));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(lfu.reigning_hot_key(), Some((&"b".to_string(), 1, Duration::from_secs(6))));
+        lfu.get("a");
+        assert_eq!(lfu.reigning_hot_key(), Some((&"a".to_string(), 2, Duration::ZERO)));
+    }
+
+    #[test]
+    fn test_remove_many() {
+        let mut lfu = LFU::new();
+        insert_accessed(&mut lfu, &[("a", 0), ("b", 1), ("c", 2), ("d", 2), ("e", 3)]);
+        let removed = lfu.remove_many(&["e", "missing", "b", "c", "e"]);
+        let expected: Vec<_> = ["e", "b", "c"].iter().map(|key| (key.to_string(), Bytes::from(key.to_string()))).collect();
+        assert_eq!(removed, expected);
+        let mut survivors = lfu.snapshot_keys();
+        survivors.sort();
+        assert_eq!(survivors, vec!["a", "d"]);
+        assert_eq!(lfu.chain_summary(), vec![(0, 1), (2, 1)]);
+        assert_eq!(lfu.len(), 2);
+        assert!(lfu.remove_many(&[]).is_empty());
+        assert_chain(&lfu);
+        // neighbouring nodes emptied by one batch
+        insert_accessed(&mut lfu, &[("x", 1), ("y", 3), ("z", 4)]);
+        assert_eq!(lfu.remove_many(&["x", "y", "d"]).len(), 3);
+        assert_eq!(lfu.chain_summary(), vec![(0, 1), (4, 1)]);
+        assert_chain(&lfu);
+        assert_eq!(lfu.remove_many(&["z", "a"]).len(), 2);
+        assert_eq!(lfu.frequency_node_count(), 0);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_max_key_len() {
+        let mut lfu = LFU::new().max_key_len(4).max_size(2);
+        lfu.insert("abcd".to_string(), Bytes::from("1"));
+        lfu.insert("efg".to_string(), Bytes::from("2"));
+        lfu.get("abcd");
+        // rejected before making room, so nothing is evicted
+        assert_eq!(lfu.try_insert("hijkl".to_string(), Bytes::from("3")), Err(InsertError::KeyTooLong { len: 5, max: 4 }));
+        assert_eq!(lfu.insert("hijkl".to_string(), Bytes::from("3")), None);
+        assert!(!lfu.contains_key("hijkl"));
+        assert_eq!(lfu.len(), 2);
+        assert_eq!(lfu.stats().evictions, 0);
+        assert_eq!(lfu.key_bytes, 7);
+        assert_chain(&lfu);
+        assert_eq!(lfu.try_insert("efg".to_string(), Bytes::from("4")), Ok(Some(Bytes::from("2"))));
+        assert_eq!(
+            InsertError::KeyTooLong { len: 5, max: 4 }.to_string(),
+            "key of 5 bytes is longer than the maximum of 4"
+        );
+    }
+
+    #[test]
+    fn test_peek_nth_coldest() {
+        let mut lfu = LFU::new().max_size(5);
+        insert_accessed(&mut lfu, &[("a", 2), ("b", 0), ("c", 1), ("d", 0), ("e", 2)]);
+        let order: Vec<_> = (0..6).map(|n| lfu.peek_nth_coldest(n).map(|(key, _)| key.clone())).collect();
+        let expected = vec![Some("b"), Some("d"), Some("c"), Some("a"), Some("e"), None];
+        assert_eq!(order, expected.into_iter().map(|key| key.map(String::from)).collect::<Vec<_>>());
+        assert_eq!(lfu.peek_nth_coldest(2), Some((&"c".to_string(), &Bytes::from("c"))));
+        // peeking is no access
+        assert_eq!(lfu.get_frequency("b"), 0);
+        assert_eq!(lfu.stats().hits, 5);
+        // the next victim is exactly what was peeked first
+        lfu.insert("f".to_string(), Bytes::from("F"));
+        assert!(!lfu.contains_key("b"));
+        assert_eq!(lfu.peek_nth_coldest(0).map(|(key, _)| key.as_str()), Some("d"));
+    }
+
+    #[test]
+    fn test_lfu_then_fifo() {
+        // b reaches frequency 1 before a, although a was inserted first
+        let fill = |lfu: &mut LFU| {
+            for key in &["a", "b", "c"] {
+                lfu.insert(key.to_string(), Bytes::from("1"));
+            }
+            lfu.get("c");
+            lfu.get("c");
+            lfu.get("b");
+            lfu.get("a");
+            lfu.insert("d".to_string(), Bytes::from("2"));
+            lfu.get("d");
+            lfu.insert("e".to_string(), Bytes::from("3"));
+        };
+        // plain LFU evicts the first to arrive at frequency 1
+        let mut lfu = LFU::new().max_size(4);
+        fill(&mut lfu);
+        assert!(!lfu.contains_key("b"));
+        assert!(lfu.contains_key("a"));
+        // FIFO evicts the first inserted among the coldest
+        let mut lfu = LFU::new().max_size(4).eviction(EvictionPolicy::LfuThenFifo);
+        fill(&mut lfu);
+        assert!(!lfu.contains_key("a"));
+        assert!(lfu.contains_key("b"));
+        let order: Vec<_> = lfu.eviction_order().map(|key| key.to_string()).collect();
+        assert_eq!(order, vec!["e", "b", "d", "c"]);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_lfu_then_fifo_node_order() {
+        let mut lfu = LFU::new().eviction(EvictionPolicy::LfuThenFifo).aging_step(2);
+        for key in ["a", "b", "c", "d", "e"] {
+            lfu.insert(key.to_string(), Bytes::from("1"));
+        }
+        for key in ["e", "c", "a", "d", "d", "b", "b", "b"] {
+            lfu.get(key);
+        }
+        let keys = |lfu: &LFU| -> Vec<Vec<String>> {
+            lfu.nodes().map(|node| node.borrow().items.iter().map(|key| key.to_string()).collect()).collect()
+        };
+        // every node lists its keys by insert, whatever order they arrived in
+        assert_eq!(keys(&lfu), vec![vec![], vec!["a", "c", "e"], vec!["d"], vec!["b"]]);
+        // merged by aging, and moved by transfer
+        lfu.tick();
+        assert_eq!(keys(&lfu), vec![vec!["a", "c", "d", "e"], vec!["b"]]);
+        let mut source = LFU::new();
+        source.insert("b".to_string(), Bytes::from("1"));
+        source.get("b");
+        lfu.remove("b");
+        lfu.insert("f".to_string(), Bytes::from("1"));
+        lfu.get("f");
+        lfu.get("c");
+        assert!(source.transfer("b", &mut lfu));
+        assert_eq!(keys(&lfu)[1], vec!["c", "f", "b"]);
+        assert_eq!(lfu.next_eviction().map(|candidate| candidate.key), Some("a".to_string()));
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        // the byte limit kicks in first
+        let mut lfu = LFU::new().max_size(10).max_bytes(10);
+        lfu.insert("a".to_string(), Bytes::from(vec![0; 4]));
+        lfu.insert("b".to_string(), Bytes::from(vec![0; 4]));
+        lfu.get("a");
+        lfu.insert("c".to_string(), Bytes::from(vec![0; 4]));
+        assert_eq!(lfu.len(), 2);
+        assert!(!lfu.contains_key("b"));
+        // a large value evicts until it fits, one too large for the cap isn't stored
+        let (_, evicted) = lfu.insert_reporting("d".to_string(), Bytes::from(vec![0; 9]));
+        assert_eq!(evicted.len(), 2);
+        assert_eq!(lfu.value_bytes, 9);
+        lfu.insert("e".to_string(), Bytes::from(vec![0; 11]));
+        assert!(!lfu.contains_key("e"));
+        assert_eq!(lfu.len(), 1);
+        // growing a value on update evicts as well
+        lfu.insert("f".to_string(), Bytes::from("1"));
+        lfu.get("f");
+        let (previous, evicted) = lfu.insert_reporting("f".to_string(), Bytes::from(vec![0; 2]));
+        assert_eq!(previous, Some(Bytes::from("1")));
+        assert_eq!(evicted, vec![("d".to_string(), Bytes::from(vec![0; 9]))]);
+        assert_eq!(lfu.value_bytes, 2);
+        assert_chain(&lfu);
+
+        // the count limit kicks in first
+        let mut lfu = LFU::new().max_size(2).max_bytes(100);
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from(vec![0; 4]));
+        }
+        assert_eq!(lfu.len(), 2);
+        assert_eq!(lfu.value_bytes, 8);
+
+        // values changed through iter_mut are accounted for on the next write
+        let mut lfu = LFU::new().max_bytes(10);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.insert("b".to_string(), Bytes::from("2"));
+        for (_, value) in lfu.iter_mut() {
+            *value = Bytes::from(vec![0; 5]);
+        }
+        lfu.remove("a");
+        lfu.insert("c".to_string(), Bytes::from("3"));
+        assert_eq!(lfu.value_bytes, 6);
+        lfu.insert("d".to_string(), Bytes::from(vec![0; 5]));
+        assert_eq!(lfu.len(), 2);
+        lfu.remove("b");
+        lfu.map_values(|_, _| Bytes::from(vec![0; 7]));
+        assert_eq!(lfu.value_bytes, 14);
+        lfu.recompute_size();
+        assert_eq!(lfu.value_bytes, 14);
+        lfu.clear();
+        assert_eq!(lfu.value_bytes, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "max_size and max_bytes can't both be 0")]
+    fn test_max_bytes_and_size_zero() {
+        LFU::new().max_bytes(0).max_size(0);
+    }
+
+    #[test]
+    fn test_with_seed() {
+        let keys = |lfu: &LFU| lfu.items.keys().map(|key| key.to_string()).collect::<Vec<_>>();
+        let fill = |mut lfu: LFU| {
+            for i in 0..100 {
+                lfu.insert(format!("key{}", i), Bytes::from("1"));
+            }
+            lfu
+        };
+        let order = keys(&fill(LFU::with_seed(7).max_size(100)));
+        assert_eq!(order.len(), 100);
+        assert_eq!(keys(&fill(LFU::with_seed(7).max_size(100))), order);
+        assert_ne!(keys(&fill(LFU::with_seed(8).max_size(100))), order);
+        let mut lfu = LFU::with_seed(7);
+        lfu.insert("a".to_string(), Bytes::from("b"));
+        assert_eq!(lfu.get("a"), Some(&Bytes::from("b")));
+    }
+
+    #[test]
+    fn test_proactive_trim() {
+        let mut lfu = LFU::new().max_size(10).proactive_trim(0.7);
+        for i in 0..10 {
+            lfu.insert(i.to_string(), Bytes::from("1"));
+            for _ in 0..i {
+                lfu.get(&i.to_string());
+            }
+        }
+        assert_eq!(lfu.len(), 10);
+        // the three coldest go, aging happens first
+        lfu.tick();
+        assert_eq!(lfu.len(), 7);
+        assert_eq!(lfu.stats().evictions, 3);
+        assert!((0..3).all(|i| !lfu.contains_key(&i.to_string())));
+        assert_eq!(lfu.get_frequency("9"), 8);
+        // at or under the low-water mark nothing gets evicted
+        lfu.tick();
+        assert_eq!(lfu.len(), 7);
+        assert_chain(&lfu);
+
+        // byte capacity is trimmed as well, also when aging is off
+        let mut lfu = LFU::new().max_bytes(10).aging_step(0).proactive_trim(0.5);
+        for key in &["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from(vec![0; 3]));
+        }
+        lfu.tick();
+        assert_eq!(lfu.len(), 1);
+        assert_eq!(lfu.value_bytes, 3);
+    }
+
+    #[cfg(feature = "log")]
+    mod logging {
+        use crate::*;
+        use bytes::Bytes;
+        use std::sync::{Mutex, Once};
+
+        // keeps every record, tests running in parallel tell theirs apart by key
+        struct TestLogger(Mutex<Vec<(log::Level, String)>>);
+
+        impl log::Log for TestLogger {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: TestLogger = TestLogger(Mutex::new(vec![]));
+        static INIT: Once = Once::new();
+
+        #[test]
+        fn test_log_records() {
+            INIT.call_once(|| {
+                log::set_logger(&LOGGER).unwrap();
+                log::set_max_level(log::LevelFilter::Trace);
+            });
+            let mut lfu = LFU::new().max_size(1);
+            lfu.insert("logged_a".to_string(), Bytes::from("1"));
+            lfu.get("logged_a");
+            lfu.get("logged_b");
+            lfu.insert("logged_b".to_string(), Bytes::from("2"));
+            let records: Vec<_> = LOGGER.0.lock().unwrap()
+                .iter()
+                .filter(|(_, message)| message.contains("logged_"))
+                .cloned()
+                .collect();
+            assert_eq!(records, vec![
+                (log::Level::Trace, "insert \"logged_a\" at frequency 0".to_string()),
+                (log::Level::Trace, "miss \"logged_b\"".to_string()),
+                (log::Level::Debug, "evict \"logged_a\" at frequency 1".to_string()),
+                (log::Level::Trace, "insert \"logged_b\" at frequency 0".to_string()),
+            ]);
+        }
+    }
+
+    #[test]
+    fn test_increment() {
+        let mut lfu = LFU::new();
+        lfu.insert("counter".to_string(), Bytes::copy_from_slice(&0u64.to_le_bytes()));
+        assert_eq!(lfu.increment("counter", 1), Some(1));
+        assert_eq!(lfu.increment("counter", 5), Some(6));
+        assert_eq!(lfu.increment("counter", 10), Some(16));
+        assert_eq!(lfu.get_frequency("counter"), 3);
+        assert_eq!(lfu.get("counter"), Some(&Bytes::copy_from_slice(&16u64.to_le_bytes())));
+        assert_eq!(lfu.increment("counter", u64::MAX), Some(u64::MAX));
+        // absent keys and values of another length are left alone
+        assert_eq!(lfu.increment("missing", 1), None);
+        lfu.insert("short".to_string(), Bytes::from("1234"));
+        assert_eq!(lfu.increment("short", 1), None);
+        assert_eq!(lfu.get_frequency("short"), 0);
+        assert_eq!(lfu.get("short"), Some(&Bytes::from("1234")));
+    }
+
+    #[test]
+    fn test_clear_values_keep_frequencies() {
+        let mut lfu = LFU::new().max_bytes(100);
+        insert_accessed(&mut lfu, &[("a", 0), ("b", 2), ("c", 5)]);
+        lfu.clear_values_keep_frequencies();
+        assert_eq!(lfu.len(), 3);
+        assert!(lfu.entries().all(|entry| entry.value.is_empty()));
+        assert_eq!(lfu.value_bytes, 0);
+        assert_eq!(lfu.chain_summary(), vec![(0, 1), (2, 1), (5, 1)]);
+        // re-warming keeps the remembered frequency
+        lfu.insert("b".to_string(), Bytes::from("2"));
+        assert_eq!(lfu.get_frequency("b"), 2);
+        assert_eq!(lfu.get("c"), Some(&Bytes::new()));
+        assert_eq!(lfu.get_frequency("c"), 6);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut lfu = LFU::new().max_size(4);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.insert("b".to_string(), Bytes::from("2"));
+        lfu.get("b");
+        let pairs = ["b", "c", "d", "e", "f"].iter().map(|key| (key.to_string(), Bytes::from("new")));
+        assert_eq!(lfu.fill(pairs), 2);
+        assert_eq!(lfu.len(), 4);
+        assert!(!lfu.contains_key("e"));
+        // existing entries are untouched, present keys aren't overwritten either
+        assert_eq!(lfu.stats().evictions, 0);
+        assert_eq!(lfu.get("a"), Some(&Bytes::from("1")));
+        assert_eq!(lfu.get("b"), Some(&Bytes::from("2")));
+        assert_eq!(lfu.get_frequency("b"), 2);
+        // a full cache takes nothing
+        assert_eq!(lfu.fill(vec![("g".to_string(), Bytes::from("3"))]), 0);
+        // the byte capacity counts too
+        let mut lfu = LFU::new().max_bytes(5);
+        let pairs = ["a", "b", "c"].iter().map(|key| (key.to_string(), Bytes::from("12")));
+        assert_eq!(lfu.fill(pairs), 2);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_decaying() {
+        let clock = ManualClock::new();
+        let half_life = Duration::from_secs(10);
+        let mut lfu = LFU::new().max_size(3).clock(clock.clone()).eviction(EvictionPolicy::Decaying { half_life });
+        lfu.insert("old".to_string(), Bytes::from("1"));
+        for _ in 0..16 {
+            lfu.get("old");
+        }
+        clock.advance(Duration::from_secs(30));
+        lfu.insert("recent".to_string(), Bytes::from("2"));
+        for _ in 0..4 {
+            lfu.get("recent");
+        }
+        clock.advance(Duration::from_secs(10));
+        // 16 halved four times against 4 halved once
+        assert_eq!(lfu.effective_frequency("old"), Some(1.0));
+        assert_eq!(lfu.effective_frequency("recent"), Some(2.0));
+        assert_eq!(lfu.get_frequency("old"), 16);
+        assert_eq!(lfu.effective_frequency("missing"), None);
+        // the old hot key goes first, plain LFU would have evicted the recent one
+        lfu.insert("new".to_string(), Bytes::from("3"));
+        lfu.get("new");
+        lfu.get("new");
+        lfu.insert("newer".to_string(), Bytes::from("4"));
+        assert!(!lfu.contains_key("old"));
+        assert!(lfu.contains_key("recent"));
+        // an access adds to the decayed count rather than restoring the full one
+        lfu.get("recent");
+        assert_eq!(lfu.effective_frequency("recent"), Some(3.0));
+        assert_chain(&lfu);
+        // a long idle hot key accessed once still loses to a recent warm one
+        let mut lfu = LFU::new().max_size(2).clock(clock.clone()).eviction(EvictionPolicy::Decaying { half_life });
+        lfu.insert("idle".to_string(), Bytes::from("1"));
+        for _ in 0..100 {
+            lfu.get("idle");
+        }
+        clock.advance(half_life * 20);
+        lfu.insert("recent".to_string(), Bytes::from("2"));
+        for _ in 0..5 {
+            lfu.get("recent");
+        }
+        lfu.get("idle");
+        assert!(lfu.effective_frequency("idle").unwrap() < 1.001);
+        lfu.insert("new".to_string(), Bytes::from("3"));
+        assert!(!lfu.contains_key("idle"));
+        assert!(lfu.contains_key("recent"));
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    #[should_panic(expected = "Decaying half_life must be positive")]
+    fn test_decaying_zero_half_life() {
+        LFU::new().eviction(EvictionPolicy::Decaying { half_life: Duration::default() });
+    }
+
+    #[test]
+    fn test_next_eviction() {
+        let clock = ManualClock::new();
+        let mut lfu = LFU::new().clock(clock.clone());
+        assert_eq!(lfu.next_eviction(), None);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        clock.advance(Duration::from_secs(2));
+        lfu.insert("b".to_string(), Bytes::from("2"));
+        clock.advance(Duration::from_secs(1));
+        let expected = EvictionCandidate {
+            key: "a".to_string(),
+            frequency: 0,
+            age: Duration::from_secs(3),
+            reason: EvictionReason::OldestTiebreak,
+        };
+        assert_eq!(lfu.next_eviction(), Some(expected));
+        lfu.get("a");
+        let candidate = lfu.next_eviction().unwrap();
+        assert_eq!((candidate.key.as_str(), candidate.reason), ("b", EvictionReason::LowestFrequency));
+        assert_eq!(candidate.reason.to_string(), "lowest frequency");
+        // entries protected by min_residency don't count as tied
+        let mut lfu = LFU::new().clock(clock.clone()).min_residency(Duration::from_secs(5));
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        clock.advance(Duration::from_secs(5));
+        lfu.insert("b".to_string(), Bytes::from("2"));
+        assert_eq!(lfu.next_eviction().map(|candidate| candidate.reason), Some(EvictionReason::LowestFrequency));
+        // the scored policies name their score
+        let mut lfu = LFU::new().eviction(EvictionPolicy::GDSF);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        assert_eq!(lfu.next_eviction().unwrap().reason.to_string(), "lowest GDSF priority");
+        // peeking changes nothing
+        assert_eq!(lfu.len(), 1);
+    }
+
+    #[test]
+    fn test_map_keys() {
+        let clock = ManualClock::new();
+        let mut lfu = LFU::new().clock(clock.clone());
+        insert_accessed(&mut lfu, &[("a", 0), ("b", 2), ("c", 5)]);
+        clock.advance(Duration::from_secs(5));
+        lfu.map_keys(|key| format!("v2:{}", key));
+        assert_eq!(lfu.reigning_hot_key(), Some((&"v2:c".to_string(), 5, Duration::from_secs(5))));
+        assert_chain(&lfu);
+        assert_eq!(lfu.len(), 3);
+        assert_eq!(lfu.key_bytes, 12);
+        for (key, frequency) in &[("a", 0), ("b", 2), ("c", 5)] {
+            assert!(!lfu.contains_key(key));
+            assert_eq!(lfu.get_frequency(&format!("v2:{}", key)), *frequency);
+        }
+        assert_eq!(lfu.get("v2:b"), Some(&Bytes::from("b")));
+        // collisions keep the hotter entry
+        lfu.map_keys(|key| if key == "v2:c" { key.to_string() } else { "v2:x".to_string() });
+        assert_chain(&lfu);
+        assert_eq!(lfu.len(), 2);
+        assert_eq!(lfu.get("v2:x"), Some(&Bytes::from("b")));
+        assert_eq!(lfu.get_frequency("v2:x"), 4);
+        assert_eq!(lfu.value_bytes, 2);
+        assert_eq!(lfu.chain_summary(), vec![(4, 1), (5, 1)]);
+        // an identity rename doesn't restart the reign either
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(lfu.reigning_hot_key(), Some((&"v2:c".to_string(), 5, Duration::from_secs(6))));
+    }
+
+    #[test]
+    fn test_miss_path() {
+        let mut lfu = LFU::new().max_size(16).min_residency(Duration::from_secs(1));
+        for i in 0..16 {
+            lfu.insert(format!("key{}", i), Bytes::from("1"));
+            lfu.get(&format!("key{}", i));
+        }
+        let misses: Vec<_> = (0..100).map(|i| format!("miss{}", i)).collect();
+        NODES_WALKED.with(|walked| walked.set(0));
+        for key in &misses {
+            assert_eq!(lfu.get(key), None);
+        }
+        // allocations are counted in tests/alloc.rs
+        assert_eq!(NODES_WALKED.with(|walked| walked.get()), 0);
+        assert_eq!(lfu.stats().misses, 100);
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.get("a");
+        // success keeps the frequency
+        assert_eq!(lfu.compare_and_swap("a", &Bytes::from("1"), Bytes::from("22")), Ok(()));
+        assert_eq!(lfu.get_frequency("a"), 1);
+        assert_eq!(lfu.value_bytes, 2);
+        // mismatch hands back the current value and changes nothing
+        assert_eq!(lfu.compare_and_swap("a", &Bytes::from("1"), Bytes::from("3")), Err(Some(Bytes::from("22"))));
+        assert_eq!(lfu.get("a"), Some(&Bytes::from("22")));
+        // absent
+        assert_eq!(lfu.compare_and_swap("b", &Bytes::from("1"), Bytes::from("3")), Err(None));
+        assert!(!lfu.contains_key("b"));
+        assert_eq!(lfu.stats().misses, 0);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_frequency_node_count() {
+        let mut lfu = LFU::new().aging_step(2);
+        assert_eq!(lfu.frequency_node_count(), 0);
+        for (key, gets) in [("a", 1), ("b", 2), ("c", 3), ("d", 3)] {
+            lfu.insert(key.to_string(), Bytes::from("1"));
+            for _ in 0..gets {
+                lfu.get(key);
+            }
+        }
+        // nodes 1, 2 and 3, c and d share one
+        assert_eq!(lfu.frequency_node_count(), 3);
+        lfu.remove("a");
+        assert_eq!(lfu.frequency_node_count(), 2);
+        // aging merges 1 and 2 into the head, leaving only 3 - 2
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.get("a");
+        lfu.tick();
+        assert_eq!(lfu.chain_summary(), vec![(0, 2), (1, 2)]);
+        assert_eq!(lfu.frequency_node_count(), 1);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_verify_on_mutation() {
+        let mut lfu = LFU::new().verify_on_mutation(true);
+        for key in ["a", "b", "c"] {
+            lfu.insert(key.to_string(), Bytes::from("1"));
+        }
+        for key in ["a", "a", "a", "a", "b", "b"] {
+            lfu.get(key);
+        }
+        lfu.remove("c");
+        lfu.tick();
+        assert_chain(&lfu);
+        // b's node jumps ahead of a's behind the cache's back, the next promotion of b notices
+        lfu.items["b"].parent.borrow_mut().value = 7;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| lfu.get("b").is_some()));
+        let message = result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(*message, "frequency chain corrupted: node at frequency 8 and its next at 3 aren't linked in order");
+    }
+
+    #[test]
+    fn test_verify_on_mutation_tail() {
+        let mut lfu = LFU::new().verify_on_mutation(true);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.get("a");
+        // the tail pointer falls behind the end of the chain
+        lfu.frequency_tail = lfu.frequency_head.clone();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            lfu.insert("b".to_string(), Bytes::from("1"))
+        }));
+        let message = result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(*message, "frequency chain corrupted: tail at frequency 0 links to frequency 1");
+    }
+
+    #[test]
+    fn test_verify_on_mutation_report() {
+        let mut lfu = LFU::new().verify_on_mutation(true).on_corruption(OnCorruption::Report);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.get("a");
+        lfu.insert("b".to_string(), Bytes::from("1"));
+        assert_eq!(lfu.stats().corruptions, 0);
+        lfu.frequency_tail = lfu.frequency_head.clone();
+        // every check of the broken tail counts, nothing panics
+        lfu.insert("c".to_string(), Bytes::from("1"));
+        lfu.remove("b");
+        assert_eq!(lfu.stats().corruptions, 2);
+        assert!(lfu.contains_key("c"));
+    }
+
+    #[test]
+    fn test_into_sorted_by_frequency() {
+        let mut lfu = LFU::new();
+        for (key, gets) in [("a", 3), ("b", 0), ("c", 5), ("d", 1), ("e", 3)] {
+            lfu.insert(key.to_string(), Bytes::from(key));
+            for _ in 0..gets {
+                lfu.get(key);
+            }
+        }
+        let entries: Vec<_> = lfu.into_sorted_by_frequency().collect();
+        let expected = [("b", 0), ("d", 1), ("a", 3), ("e", 3), ("c", 5)];
+        assert_eq!(entries.len(), expected.len());
+        for ((key, frequency, value), (expected_key, expected_frequency)) in entries.into_iter().zip(expected) {
+            assert_eq!((key.as_str(), frequency), (expected_key, expected_frequency));
+            assert_eq!(value, Bytes::from(expected_key));
+        }
+        // every entry comes out exactly once, then the iterator ends
+        let mut lfu = LFU::new();
+        lfu.insert("a".to_string(), Bytes::from("b"));
+        lfu.insert("c".to_string(), Bytes::from("d"));
+        let mut entries = lfu.into_sorted_by_frequency();
+        assert_eq!(entries.next().map(|(key, ..)| key), Some("a".to_string()));
+        assert_eq!(entries.next().map(|(key, ..)| key), Some("c".to_string()));
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn test_from_frequencies() {
+        let entries = vec![
+            ("a".to_string(), Bytes::from("1"), 4),
+            ("b".to_string(), Bytes::from("2"), 0),
+            ("c".to_string(), Bytes::from("3"), 2),
+            ("d".to_string(), Bytes::from("4"), 2),
+            ("a".to_string(), Bytes::from("5"), 7),
+            ("e".to_string(), Bytes::from("6"), 1),
+        ];
+        let mut lfu = LFU::from_frequencies(entries.clone(), 8);
+        assert_chain(&lfu);
+        assert_eq!(lfu.len(), 5);
+        assert_eq!(lfu.chain_summary(), vec![(0, 1), (1, 1), (2, 2), (7, 1)]);
+        // the later entry of a wins
+        assert_eq!(lfu.get_frequency("a"), 7);
+        assert_eq!(lfu.peek_nth_coldest(2).map(|(key, _)| key.to_string()), Some("c".to_string()));
+        assert_eq!(lfu.get("a"), Some(&Bytes::from("5")));
+        assert_eq!(lfu.get_frequency("a"), 8);
+        assert_eq!(lfu.stats().hits, 1);
+        // over capacity the coldest are dropped, c beats d on their tie by coming first
+        let mut lfu = LFU::from_frequencies(entries, 2);
+        assert_chain(&lfu);
+        assert_eq!(lfu.chain_summary(), vec![(2, 1), (7, 1)]);
+        assert!(lfu.contains_key("c"));
+        assert!(!lfu.contains_key("d"));
+        lfu.insert("f".to_string(), Bytes::from("7"));
+        assert!(!lfu.contains_key("c"));
+        assert_eq!(LFU::from_frequencies(vec![("a".to_string(), Bytes::new(), 1)], 0).len(), 0);
+    }
+
+    #[test]
+    fn test_max_bytes_oversized_update() {
+        let mut lfu = LFU::new().max_bytes(10);
+        for key in ["a", "b", "c", "d"] {
+            lfu.insert(key.to_string(), Bytes::from("12"));
+        }
+        lfu.get("a");
+        lfu.get("a");
+        let (previous, evicted) = lfu.insert_reporting("a".to_string(), Bytes::from(vec![0; 11]));
+        assert_eq!(previous, Some(Bytes::from("12")));
+        assert!(evicted.is_empty());
+        assert!(!lfu.contains_key("a"));
+        assert_eq!(lfu.len(), 3);
+        assert_eq!(lfu.value_bytes, 6);
+        assert_eq!(lfu.stats().evictions, 0);
+        assert_chain(&lfu);
+    }
+
+    #[test]
+    fn test_compare_and_swap_max_bytes() {
+        let mut lfu = LFU::new().max_bytes(10);
+        for key in ["a", "b", "c", "d"] {
+            lfu.insert(key.to_string(), Bytes::from("12"));
+        }
+        lfu.get("a");
+        // never fits even for a mismatch, which leaves everything as it is
+        let oversized = Bytes::from(vec![0; 11]);
+        assert_eq!(lfu.compare_and_swap("a", &Bytes::from("1"), oversized.clone()), Err(Some(Bytes::from("12"))));
+        assert_eq!(lfu.len(), 4);
+        assert_eq!(lfu.value_bytes, 8);
+        // fits once b is evicted
+        assert_eq!(lfu.compare_and_swap("a", &Bytes::from("12"), Bytes::from("12345")), Ok(()));
+        assert!(!lfu.contains_key("b"));
+        assert_eq!(lfu.len(), 3);
+        // c grows over the cap while being the coldest itself, the swap doesn't report success
+        assert_eq!(lfu.compare_and_swap("c", &Bytes::from("12"), Bytes::from("123456")), Err(None));
+        assert!(!lfu.contains_key("c"));
+        assert!(lfu.contains_key("d"));
+        assert_eq!(lfu.value_bytes, 7);
+        // a retry loop swapping in a value that never fits ends, with d gone rather than stale
+        let mut attempts = 0;
+        let swapped = loop {
+            attempts += 1;
+            let current = match lfu.get("d") {
+                Some(current) => current.clone(),
+                None => break false,
+            };
+            match lfu.compare_and_swap("d", &current, oversized.clone()) {
+                Ok(()) => break true,
+                Err(Some(_)) => continue,
+                Err(None) => break false,
+            }
+        };
+        assert!(!swapped);
+        assert_eq!(attempts, 1);
+        assert!(!lfu.contains_key("d"));
+        assert_eq!(lfu.len(), 1);
+        assert_eq!(lfu.value_bytes, 5);
+        assert_eq!(lfu.stats().evictions, 2);
+        assert_chain(&lfu);
     }
 }
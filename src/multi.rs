@@ -0,0 +1,153 @@
+//! Multimap flavour of the cache, every key holds a list of values
+
+use crate::LFU;
+use bytes::Bytes;
+use std::collections::HashMap;
+
+/// Cache where `insert` appends to the values of a key rather than overwriting them. Frequency
+/// and capacity are tracked per key, so all values of a key share its frequency, count as a
+/// single entry and get evicted together.
+#[derive(Debug, Default)]
+pub struct MultiLFU {
+    // tracks keys only, every entry holds an empty placeholder value
+    lfu: LFU,
+    values: HashMap<String, Vec<Bytes>>,
+}
+
+impl MultiLFU {
+    pub fn new() -> Self {
+        MultiLFU { lfu: LFU::new(), values: HashMap::new() }
+    }
+    ///
+    /// Builder for max_size, the number of keys kept before evicting, regardless of how many
+    /// values each of them holds
+    ///
+    /// ```
+    /// use lfu::MultiLFU;
+    /// let lfu = MultiLFU::new().max_size(1024);
+    /// ```
+    ///
+    pub fn max_size(mut self, size: usize) -> Self {
+        self.lfu = self.lfu.max_size(size);
+        self
+    }
+
+    ///
+    /// Appends a value to the key, evicting the least frequently used key with all its values
+    /// when a new key needs room
+    ///
+    /// ```
+    /// use lfu::MultiLFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = MultiLFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("a".to_string(), Bytes::from("c"));
+    /// assert_eq!(lfu.get("a"), Some(&[Bytes::from("b"), Bytes::from("c")][..]));
+    /// ```
+    pub fn insert(&mut self, key: String, value: Bytes) {
+        let (_, evicted) = self.lfu.insert_reporting(key.clone(), Bytes::new());
+        for (evicted, _) in evicted {
+            self.values.remove(&evicted);
+        }
+        if self.lfu.contains_key(&key) {
+            self.values.entry(key).or_default().push(value);
+        }
+    }
+
+    ///
+    /// All values of the key in insertion order, counting one access of the key
+    ///
+    /// ```
+    /// use lfu::MultiLFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = MultiLFU::new();
+    /// assert_eq!(lfu.get("a"), None);
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.get("a"), Some(&[Bytes::from("b")][..]));
+    /// ```
+    pub fn get(&mut self, key: &str) -> Option<&[Bytes]> {
+        self.lfu.get(key)?;
+        self.values.get(key).map(Vec::as_slice)
+    }
+
+    ///
+    /// Removes the key, returning all of its values
+    ///
+    /// ```
+    /// use lfu::MultiLFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = MultiLFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// assert_eq!(lfu.remove("a"), Some(vec![Bytes::from("b")]));
+    /// assert_eq!(lfu.remove("a"), None);
+    /// ```
+    pub fn remove(&mut self, key: &str) -> Option<Vec<Bytes>> {
+        self.lfu.remove(key)?;
+        self.values.remove(key)
+    }
+
+    ///
+    /// Frequency of the key, shared by all of its values
+    ///
+    /// ```
+    /// use lfu::MultiLFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = MultiLFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.get("a");
+    /// assert_eq!(lfu.get_frequency("a"), 1);
+    /// ```
+    pub fn get_frequency(&mut self, key: &str) -> usize {
+        self.lfu.get_frequency(key)
+    }
+
+    ///
+    /// Number of keys stored, however many values they hold
+    ///
+    /// ```
+    /// use lfu::MultiLFU;
+    /// use bytes::Bytes;
+    /// let mut lfu = MultiLFU::new();
+    /// lfu.insert("a".to_string(), Bytes::from("b"));
+    /// lfu.insert("a".to_string(), Bytes::from("c"));
+    /// assert_eq!(lfu.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.lfu.len()
+    }
+
+    ///
+    /// Checks whether the cache holds no keys
+    ///
+    /// ```
+    /// use lfu::MultiLFU;
+    /// assert!(MultiLFU::new().is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.lfu.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MultiLFU;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_multi_lfu() {
+        let mut lfu = MultiLFU::new().max_size(2);
+        lfu.insert("a".to_string(), Bytes::from("1"));
+        lfu.insert("a".to_string(), Bytes::from("2"));
+        lfu.insert("b".to_string(), Bytes::from("3"));
+        assert_eq!(lfu.len(), 2);
+        assert_eq!(lfu.get("a"), Some(&[Bytes::from("1"), Bytes::from("2")][..]));
+        // appending is not an access, the key's frequency is shared by its values
+        assert_eq!(lfu.get_frequency("a"), 1);
+        // b is the victim and leaves with all of its values
+        lfu.insert("c".to_string(), Bytes::from("4"));
+        assert_eq!(lfu.get("b"), None);
+        assert_eq!(lfu.remove("a"), Some(vec![Bytes::from("1"), Bytes::from("2")]));
+        assert_eq!(lfu.get("a"), None);
+        assert_eq!(lfu.len(), 1);
+    }
+}
@@ -0,0 +1,52 @@
+//! Allocation counts of the hot paths. Lives in its own test binary, so that the counting
+//! allocator is installed for these tests only.
+
+use bytes::Bytes;
+use lfu::LFU;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Duration;
+
+// counts allocations per thread, so tests can assert that a path doesn't allocate
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|allocations| allocations.get())
+}
+
+#[test]
+fn test_miss_path_does_not_allocate() {
+    let mut lfu = LFU::new().max_size(16).min_residency(Duration::from_secs(1));
+    for i in 0..16 {
+        lfu.insert(format!("key{}", i), Bytes::from("1"));
+        lfu.get(&format!("key{}", i));
+    }
+    let misses: Vec<_> = (0..100).map(|i| format!("miss{}", i)).collect();
+    let before = allocations();
+    for key in &misses {
+        assert_eq!(lfu.get(key), None);
+    }
+    assert_eq!(allocations(), before);
+    assert_eq!(lfu.stats().misses, 100);
+    // the counter does see allocations
+    let _key = "key".to_string();
+    assert!(allocations() > before);
+}